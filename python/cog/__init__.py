@@ -1,7 +1,15 @@
 from pydantic import BaseModel
 
 from .predictor import BasePredictor
-from .types import ConcatenateIterator, File, Input, Path, Secret
+from .server.scope import record_metric, set_response_header
+from .types import (
+    AsyncConcatenateIterator,
+    ConcatenateIterator,
+    File,
+    Input,
+    Path,
+    Secret,
+)
 
 try:
     from ._version import __version__
@@ -11,6 +19,7 @@ except ImportError:
 
 __all__ = [
     "__version__",
+    "AsyncConcatenateIterator",
     "BaseModel",
     "BasePredictor",
     "ConcatenateIterator",
@@ -18,4 +27,6 @@ __all__ = [
     "Input",
     "Path",
     "Secret",
+    "record_metric",
+    "set_response_header",
 ]
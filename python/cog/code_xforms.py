@@ -1,9 +1,21 @@
 import ast
 import re
 import types
-from typing import Optional, Set, Union
-
-COG_IMPORT_MODULES = {"cog", "typing", "sys", "os", "functools", "pydantic", "numpy"}
+from typing import List, Optional, Set, Union
+
+COG_IMPORT_MODULES = {
+    "cog",
+    "typing",
+    "typing_extensions",
+    "sys",
+    "os",
+    "functools",
+    "pydantic",
+    "numpy",
+}
+COG_MODULE_CONSTANTS = {"COG_OUTPUT_EXAMPLE"}
+# Decorators that change how a method is called, and so its signature.
+KEPT_DECORATORS = {"staticmethod", "classmethod"}
 
 
 def load_module_from_string(
@@ -67,6 +79,33 @@ def extract_function_source(source_code: str, function_name: str) -> str:
     return extractor.function_source if extractor.function_source else ""
 
 
+def strip_decorators(node: Union[ast.FunctionDef, ast.AsyncFunctionDef]) -> None:
+    """
+    Removes decorators from a function definition, so that the stripped source
+    doesn't depend on where they were defined or imported from. Decorators are
+    expected to preserve the signature of what they wrap (with functools.wraps),
+    so the signature is the same either way.
+    Args:
+        node: The function definition to transform in place.
+    """
+    node.decorator_list = [
+        d
+        for d in node.decorator_list
+        if isinstance(d, ast.Name) and d.id in KEPT_DECORATORS
+    ]
+
+
+def empty_body(node: Union[ast.FunctionDef, ast.AsyncFunctionDef]) -> List[ast.stmt]:
+    """
+    Returns a body of `return None` for a function, keeping its docstring, as
+    that can describe the function's inputs.
+    """
+    body: List[ast.stmt] = [ast.Return(value=ast.Constant(value=None))]
+    if ast.get_docstring(node, clean=False) is not None:
+        body.insert(0, node.body[0])
+    return body
+
+
 def make_class_methods_empty(source_code: Union[str, ast.AST], class_name: str) -> str:
     """
     Transforms the source code of a specified class to remove the bodies of all its methods
@@ -82,9 +121,10 @@ def make_class_methods_empty(source_code: Union[str, ast.AST], class_name: str)
         def visit_ClassDef(self, node: ast.ClassDef) -> Optional[ast.AST]:
             if node.name == class_name:
                 for body_item in node.body:
-                    if isinstance(body_item, ast.FunctionDef):
+                    if isinstance(body_item, (ast.FunctionDef, ast.AsyncFunctionDef)):
                         # Replace the body of the method with `return None`
-                        body_item.body = [ast.Return(value=ast.Constant(value=None))]
+                        body_item.body = empty_body(body_item)
+                        strip_decorators(body_item)
                 return node
 
     tree = source_code if isinstance(source_code, ast.AST) else ast.parse(source_code)
@@ -169,7 +209,8 @@ def make_function_empty(source_code: Union[str, ast.AST], function_name: str) ->
         def visit_FunctionDef(self, node: ast.FunctionDef) -> Optional[ast.AST]:
             if node.name == function_name:
                 # Replace the body of the function with `return None`
-                node.body = [ast.Return(value=ast.Constant(value=None))]
+                node.body = empty_body(node)
+                strip_decorators(node)
                 return node
 
     tree = source_code if isinstance(source_code, ast.AST) else ast.parse(source_code)
@@ -211,6 +252,32 @@ def extract_specific_imports(
     return "\n".join(extractor.imports)
 
 
+def extract_module_assignments(
+    source_code: Union[str, ast.AST], names: Set[str]
+) -> str:
+    """
+    Extracts top-level assignments to the specified names from the source code.
+    Args:
+        source_code: The Python source code as a string or an AST node.
+        names: A set of variable names for which to extract assignments.
+    Returns:
+        The matching assignment statements, joined by newlines.
+    """
+    tree = source_code if isinstance(source_code, ast.AST) else ast.parse(source_code)
+    assignments = []
+    for node in getattr(tree, "body", []):
+        if isinstance(node, ast.Assign):
+            targets = node.targets
+        elif isinstance(node, ast.AnnAssign) and node.value is not None:
+            targets = [node.target]
+        else:
+            continue
+        if any(isinstance(t, ast.Name) and t.id in names for t in targets):
+            assignments.append(ast.unparse(node))
+
+    return "\n".join(assignments)
+
+
 def strip_model_source_code(
     source_code: str, class_name: str, method_name: str
 ) -> Optional[str]:
@@ -227,6 +294,9 @@ def strip_model_source_code(
         Returns None if neither the class nor the function specified could be found or processed.
     """
     imports = extract_specific_imports(source_code, COG_IMPORT_MODULES)
+    constants = extract_module_assignments(source_code, COG_MODULE_CONSTANTS)
+    if constants:
+        imports += "\n\n" + constants
     class_source = (
         None if not class_name else extract_class_source(source_code, class_name)
     )
@@ -1,10 +1,16 @@
+import argparse
 import ast
+import copy
 import json
+import os
 import sys
+import time
 import types
 import typing
 from pathlib import Path
 
+from ..docstrings import parse_parameter_descriptions
+
 try:
     assert ast.unparse
 except (AssertionError, AttributeError):
@@ -15,6 +21,13 @@ BASE_SCHEMA = """
 {
   "components": {
     "schemas": {
+      "CancelRequest": {
+        "properties": {
+          "reason": { "title": "Reason", "type": "string" }
+        },
+        "title": "CancelRequest",
+        "type": "object"
+      },
       "HTTPValidationError": {
         "properties": {
           "detail": {
@@ -47,7 +60,7 @@ BASE_SCHEMA = """
             "type": "string"
           },
           "webhook_events_filter": {
-            "default": ["start", "output", "logs", "completed"],
+            "default": ["start", "output", "logs", "completed", "canceled"],
             "items": { "$ref": "#/components/schemas/WebhookEvent" },
             "type": "array"
           }
@@ -57,6 +70,7 @@ BASE_SCHEMA = """
       },
       "PredictionResponse": {
         "properties": {
+          "cancel_reason": { "title": "Cancel Reason", "type": "string" },
           "completed_at": {
             "format": "date-time",
             "title": "Completed At",
@@ -79,7 +93,13 @@ BASE_SCHEMA = """
             "type": "string"
           },
           "status": { "$ref": "#/components/schemas/Status" },
-          "version": { "title": "Version", "type": "string" }
+          "traceback": { "title": "Traceback", "type": "string" },
+          "version": { "title": "Version", "type": "string" },
+          "warnings": {
+            "items": { "type": "string" },
+            "title": "Warnings",
+            "type": "array"
+          }
         },
         "title": "PredictionResponse",
         "type": "object"
@@ -106,7 +126,7 @@ BASE_SCHEMA = """
       },
       "WebhookEvent": {
         "description": "An enumeration.",
-        "enum": ["start", "output", "logs", "completed"],
+        "enum": ["start", "output", "logs", "completed", "canceled", "starting"],
         "title": "WebhookEvent",
         "type": "string"
       }
@@ -188,6 +208,37 @@ BASE_SCHEMA = """
       }
     },
     "/predictions/{prediction_id}": {
+      "get": {
+        "description": "Get the current state of a prediction",
+        "operationId": "get_prediction_predictions__prediction_id__get",
+        "parameters": [
+          {
+            "in": "path",
+            "name": "prediction_id",
+            "required": true,
+            "schema": { "title": "Prediction ID", "type": "string" }
+          }
+        ],
+        "responses": {
+          "200": {
+            "content": {
+              "application/json": {
+                "schema": { "$ref": "#/components/schemas/PredictionResponse" }
+              }
+            },
+            "description": "Successful Response"
+          },
+          "422": {
+            "content": {
+              "application/json": {
+                "schema": { "$ref": "#/components/schemas/HTTPValidationError" }
+              }
+            },
+            "description": "Validation Error"
+          }
+        },
+        "summary": "Get Prediction"
+      },
       "put": {
         "description": "Run a single prediction on the model (idempotent creation).",
         "operationId": "predict_idempotent_predictions__prediction_id__put",
@@ -249,6 +300,13 @@ BASE_SCHEMA = """
             "schema": { "title": "Prediction ID", "type": "string" }
           }
         ],
+        "requestBody": {
+          "content": {
+            "application/json": {
+              "schema": { "$ref": "#/components/schemas/CancelRequest" }
+            }
+          }
+        },
         "responses": {
           "200": {
             "content": {
@@ -297,6 +355,7 @@ OPENAPI_TYPES = {
     "int": "integer",
     "float": "number",
     "bool": "boolean",
+    "bytes": "string",
     "list": "array",
     "cog.Path": "string",
     "cog.File": "string",
@@ -319,6 +378,7 @@ if typing.TYPE_CHECKING:
         "int | float | str | list[JSONObject] | JSONDict | None"
     )
     JSONDict: "typing.TypeAlias" = "dict[str, JSONObject]"
+    EnumMembers: "typing.TypeAlias" = "dict[str, dict[str, AstVal]]"
 
 
 def to_serializable(val: "AstVal") -> "JSONObject":
@@ -333,7 +393,7 @@ def to_serializable(val: "AstVal") -> "JSONObject":
         return val
 
 
-def get_value(node: ast.AST) -> "AstVal":
+def get_value(node: ast.AST, enums: "EnumMembers | None" = None) -> "AstVal":
     """Return the value of constant or list of constants"""
     if isinstance(node, ast.Constant):
         return node.value
@@ -342,13 +402,74 @@ def get_value(node: ast.AST) -> "AstVal":
         return node.s
     if isinstance(node, ast.Num):
         return node.n
-    if isinstance(node, (ast.List, ast.Tuple)):
-        return [get_value(e) for e in node.elts]
+    if isinstance(node, (ast.List, ast.Tuple, ast.Set)):
+        return [get_value(e, enums) for e in node.elts]
     if isinstance(node, ast.UnaryOp) and isinstance(node.op, ast.USub):
-        return -typing.cast(typing.Union[int, float, complex], get_value(node.operand))
+        value = get_value(node.operand, enums)
+        return -typing.cast(typing.Union[int, float, complex], value)
+    if isinstance(node, ast.Attribute) and enums:
+        # e.g. Quality.HIGH.value or Quality.HIGH.name
+        member = node.value
+        if (
+            node.attr in ("value", "name")
+            and isinstance(member, ast.Attribute)
+            and isinstance(member.value, ast.Name)
+            and member.attr in enums.get(member.value.id, {})
+        ):
+            if node.attr == "name":
+                return member.attr
+            return enums[member.value.id][member.attr]
     raise ValueError("Unexpected node type", type(node))
 
 
+def parse_when(node: ast.AST, name: str) -> "JSONDict":
+    """Return the condition from Input(when=...), which must be a dict literal"""
+    if isinstance(node, ast.Dict):
+        when: JSONDict = {}
+        try:
+            for key, value in zip(node.keys, node.values):
+                if key is None:
+                    break  # e.g. {**conditions}
+                when[str(get_value(key))] = to_serializable(get_value(value))
+            else:
+                return when
+        except ValueError:
+            pass
+    raise ValueError(
+        f"Input(when=...) for '{name}' must be a dict literal of constants",
+        ast.unparse(node),
+    )
+
+
+def parse_enums(tree: ast.AST) -> "EnumMembers":
+    """Find the members of top-level Enum classes, and their literal values"""
+    assert isinstance(tree, ast.Module)
+    enums: EnumMembers = {}
+    for node in tree.body:
+        if not isinstance(node, ast.ClassDef):
+            continue
+        # Enum, IntEnum, StrEnum, enum.Enum, ...
+        if not any(
+            isinstance(base, (ast.Name, ast.Attribute))
+            and resolve_name(base).endswith("Enum")
+            for base in node.bases
+        ):
+            continue
+        members: "dict[str, AstVal]" = {}
+        for item in node.body:
+            if (
+                isinstance(item, ast.Assign)
+                and len(item.targets) == 1
+                and isinstance(item.targets[0], ast.Name)
+            ):
+                try:
+                    members[item.targets[0].id] = get_value(item.value)
+                except ValueError:
+                    continue  # e.g. auto()
+        enums[node.name] = members
+    return enums
+
+
 def get_annotation(node: "ast.AST | None") -> str:
     """Return the annotation as a string"""
     if isinstance(node, ast.Name):
@@ -360,6 +481,22 @@ def get_annotation(node: "ast.AST | None") -> str:
     raise ValueError("Unexpected annotation type", type(node))
 
 
+def get_array_annotation(node: "ast.AST | None") -> "tuple[str, bool] | None":
+    """
+    Return the item type of a list[X] or set[X] annotation, and whether it's a
+    set, if it is one
+    """
+    if (
+        isinstance(node, ast.Subscript)
+        and isinstance(node.value, (ast.Name, ast.Attribute))
+        and resolve_name(node.value) in ("list", "List", "set", "Set")
+    ):
+        # Python 3.8 wraps the subscript in an ast.Index
+        item = getattr(node.slice, "value", node.slice)
+        return get_annotation(item), resolve_name(node.value) in ("set", "Set")
+    return None
+
+
 def get_call_name(call: ast.Call) -> str:
     """Try to get the name of a Call"""
     if isinstance(call.func, ast.Name):
@@ -372,7 +509,7 @@ def get_call_name(call: ast.Call) -> str:
 def parse_args(tree: ast.AST) -> "list[tuple[ast.arg, ast.expr | types.EllipsisType]]":
     """Parse argument, default pairs from a file with a predict function"""
     predict = find(tree, "predict")
-    assert isinstance(predict, ast.FunctionDef)
+    assert isinstance(predict, (ast.FunctionDef, ast.AsyncFunctionDef))
     args = predict.args.args  # [-len(defaults) :]
     # use Ellipsis instead of None here to distinguish a default of None
     defaults = [...] * (len(args) - len(predict.args.defaults)) + predict.args.defaults
@@ -432,9 +569,19 @@ def parse_class(classdef: ast.AST) -> "JSONDict":
 BASE_TYPES = ["str", "int", "float", "bool", "File", "Path"]
 
 
+def parse_forward_ref(node: ast.expr) -> ast.expr:
+    """Parse a string annotation like "MyOutput" into the expression it names"""
+    if isinstance(node, ast.Constant) and isinstance(node.value, str):
+        return ast.parse(node.value, mode="eval").body
+    return node
+
+
 def resolve_name(node: ast.expr) -> str:
     if isinstance(node, ast.Name):
         return node.id
+    if isinstance(node, ast.Constant) and isinstance(node.value, str):
+        # a forward reference, e.g. Iterator["MyOutput"]
+        return resolve_name(parse_forward_ref(node))
     if isinstance(node, ast.Index):
         # deprecated, but needed for py3.8
         return resolve_name(node.value)  # type: ignore
@@ -449,7 +596,7 @@ def parse_return_annotation(
     tree: ast.AST, fn: str = "predict"
 ) -> "tuple[JSONDict, JSONDict]":
     predict = find(tree, fn)
-    if not isinstance(predict, ast.FunctionDef):
+    if not isinstance(predict, (ast.FunctionDef, ast.AsyncFunctionDef)):
         raise ValueError("Could not find predict function")
     annotation = predict.returns
     if not annotation:
@@ -467,6 +614,9 @@ For example:
         ...
 """
         )
+    # with `from __future__ import annotations`, output types defined later in
+    # the file are often written as strings, e.g. -> "MyOutput"
+    annotation = parse_forward_ref(annotation)
     # attributes should be resolved to names, maybe blindly
     # subscript values are iterator or
     name = resolve_name(annotation)
@@ -500,7 +650,91 @@ For example:
     }
 
 
-KEPT_ATTRS = ("description", "default", "ge", "le", "max_length", "min_length", "regex")
+def parse_output_example(tree: ast.AST) -> "tuple[bool, JSONObject]":
+    """Find a module-level COG_OUTPUT_EXAMPLE assignment, if there is one"""
+    assert isinstance(tree, ast.Module)
+    for node in tree.body:
+        if (
+            isinstance(node, ast.Assign)
+            and len(node.targets) == 1
+            and isinstance(node.targets[0], ast.Name)
+            and node.targets[0].id == "COG_OUTPUT_EXAMPLE"
+        ):
+            return True, to_serializable(get_value(node.value))
+    return False, None
+
+
+# Empty defaults for the factories that Input(default_factory=...) supports.
+DEFAULT_FACTORIES: "dict[str, JSONObject]" = {
+    "list": [],
+    "tuple": [],
+    "set": [],
+    "dict": {},
+}
+
+
+def get_default_factory_value(node: ast.AST) -> "JSONObject":
+    """Return the empty default that a default_factory stands for"""
+    if isinstance(node, ast.Name) and node.id in DEFAULT_FACTORIES:
+        return copy.deepcopy(DEFAULT_FACTORIES[node.id])
+    raise ValueError("Unsupported default_factory", ast.unparse(node))
+
+
+KEPT_ATTRS = (
+    "description",
+    "default",
+    "ge",
+    "le",
+    "max_length",
+    "min_length",
+    "regex",
+    "minItems",
+    "maxItems",
+    "deprecated",
+)
+
+# Passed through to the schema as they are, unless the type already set them.
+PASSTHROUGH_ATTRS = ("example", "format")
+
+
+def unwrap_annotated(
+    node: "ast.expr | None",
+) -> "tuple[ast.expr | None, ast.Call | None]":
+    """
+    Return the type of an Annotated[T, Input(...)] annotation and its Input()
+    call, or the annotation as it is if it isn't Annotated
+    """
+    if not (
+        isinstance(node, ast.Subscript)
+        and isinstance(node.value, (ast.Name, ast.Attribute))
+        and resolve_name(node.value) == "Annotated"
+    ):
+        return node, None
+    # Python 3.8 wraps the subscript in an ast.Index
+    args = getattr(node.slice, "value", node.slice)
+    assert isinstance(args, ast.Tuple)
+    annotation, *metadata = args.elts
+    for meta in metadata:
+        if isinstance(meta, ast.Call) and get_call_name(meta) == "Input":
+            return annotation, meta
+    return annotation, None
+
+
+def parse_input_call(call: ast.Call, name: str, enums: "EnumMembers") -> "JSONDict":
+    """Parse the keyword arguments of an Input() call"""
+    kws: JSONDict = {}
+    for kw in call.keywords:
+        if kw.arg is None:
+            msg = "unknown argument for Input"
+            raise ValueError(msg)
+        if kw.arg == "default_factory":
+            kws["default"] = get_default_factory_value(kw.value)
+            continue
+        if kw.arg == "when":
+            kws["when"] = parse_when(kw.value, name)
+            continue
+        kws[kw.arg] = to_serializable(get_value(kw.value, enums))
+    return kws
 
 
 def extract_info(code: str) -> "JSONDict":
@@ -510,30 +744,91 @@ def extract_info(code: str) -> "JSONDict":
     inputs: JSONDict = {"title": "Input", "type": "object", "properties": properties}
     required: list[str] = []
     schemas: JSONDict = {}
+    enums = parse_enums(tree)
+    predict = find(tree, "predict")
+    assert isinstance(predict, (ast.FunctionDef, ast.AsyncFunctionDef))
+    descriptions = parse_parameter_descriptions(ast.get_docstring(predict))
     for arg, default in parse_args(tree):
         if arg.arg == "self":
             continue
-        if isinstance(default, ast.Call) and get_call_name(default) == "Input":
-            kws = {}
-            for kw in default.keywords:
-                if kw.arg is None:
-                    msg = "unknown argument for Input"
-                    raise ValueError(msg)
-                kws[kw.arg] = to_serializable(get_value(kw.value))
-        elif isinstance(default, (ast.Constant, ast.List, ast.Tuple, ast.Str, ast.Num)):
-            kws = {"default": to_serializable(get_value(default))}  # could be None
+        annotation_node, annotated_input = unwrap_annotated(arg.annotation)
+        if annotated_input is not None:
+            if isinstance(default, ast.Call) and get_call_name(default) == "Input":
+                raise ValueError(
+                    f"'{arg.arg}' has an Input() in both its annotation and its default"
+                )
+            kws = parse_input_call(annotated_input, arg.arg, enums)
+            if default != ...:
+                kws["default"] = to_serializable(get_value(default, enums))
+        elif isinstance(default, ast.Call) and get_call_name(default) == "Input":
+            kws = parse_input_call(default, arg.arg, enums)
+        elif isinstance(
+            default,
+            (
+                ast.Constant,
+                ast.List,
+                ast.Tuple,
+                ast.Set,
+                ast.Str,
+                ast.Num,
+                ast.Attribute,
+            ),
+        ):
+            # could be None
+            kws = {"default": to_serializable(get_value(default, enums))}
         elif default == ...:  # no default
             kws = {}
         else:
             raise ValueError("Unexpected default value", default)
+        # fall back to the description in the docstring, if there is one
+        if "description" not in kws and arg.arg in descriptions:
+            kws["description"] = descriptions[arg.arg]
         input: JSONDict = {"x-order": len(properties)}
+        array_annotation = get_array_annotation(annotation_node)
+        if array_annotation is not None:
+            annotation = "list"
+            item_annotation, is_set = array_annotation
+            items: JSONDict = {"type": OPENAPI_TYPES.get(item_annotation, "string")}
+            if item_annotation in ("Path", "File"):
+                items["format"] = "uri"
+            input["items"] = items
+            if is_set:
+                input["uniqueItems"] = True
+            # min_length and max_length limit the number of items of lists
+            for attr, key in (("min_length", "minItems"), ("max_length", "maxItems")):
+                if attr in kws:
+                    kws[key] = kws.pop(attr)
+        else:
+            annotation = get_annotation(annotation_node)
+        for attr, key in (("min_items", "minItems"), ("max_items", "maxItems")):
+            if attr in kws:
+                if array_annotation is None:
+                    raise ValueError(
+                        f"Input(min_items=..., max_items=...) for '{arg.arg}' "
+                        "can only be used with list inputs"
+                    )
+                kws[key] = kws.pop(attr)
         # need to handle other types?
-        arg_type = OPENAPI_TYPES.get(get_annotation(arg.annotation), "string")
-        if get_annotation(arg.annotation) in ("Path", "File"):
+        arg_type = OPENAPI_TYPES.get(annotation, "string")
+        if annotation in ("Path", "File"):
             input["format"] = "uri"
+        if annotation == "bytes":
+            input["format"] = "byte"
+        if annotation == "Secret":
+            input["format"] = "password"
+            input["writeOnly"] = True
+            input["x-cog-secret"] = True
         for attr in KEPT_ATTRS:
             if attr in kws:
                 input[attr] = kws[attr]
+        for attr in PASSTHROUGH_ATTRS:
+            if attr in kws:
+                input.setdefault(attr, kws[attr])
+        if input.get("default") is not None and "example" not in input:
+            if annotation != "Secret":
+                input["example"] = input["default"]
+        if "when" in kws:
+            input["x-cog-when"] = kws["when"]
         if "default" not in input:
             required.append(arg.arg)
         if "choices" in kws and isinstance(kws["choices"], list):
@@ -546,7 +841,7 @@ def extract_info(code: str) -> "JSONDict":
                 "description": "An enumeration.",
             }
         else:
-            input["title"] = arg.arg.replace("_", " ").title()
+            input["title"] = kws.get("title") or arg.arg.replace("_", " ").title()
             input["type"] = arg_type
         properties[arg.arg] = input
     if required:
@@ -563,6 +858,9 @@ def extract_info(code: str) -> "JSONDict":
     # trust me, typechecker, I know BASE_SCHEMA
     x: JSONDict = schema["components"]["schemas"]  # type: ignore
     x.update(components)
+    has_example, example = parse_output_example(tree)
+    if has_example:
+        x["Output"]["example"] = example  # type: ignore
     return schema
 
 
@@ -570,10 +868,76 @@ def extract_file(fname: "str | Path") -> "JSONObject":
     return extract_info(open(fname, encoding="utf-8").read())
 
 
+# How often --watch checks the file, and how long it waits for the file to
+# stop changing before regenerating, so that a burst of saves only does it once.
+WATCH_POLL_INTERVAL = 0.1
+WATCH_DEBOUNCE = 0.3
+
+
+def write_schema(schema: "JSONObject", out: "str | None") -> None:
+    """Print the schema, or write it to out if given"""
+    if out is None:
+        print(json.dumps(schema), flush=True)
+        return
+    # Replace the file in one go, so anything reading it never sees half.
+    tmp = f"{out}.tmp"
+    with open(tmp, "w", encoding="utf-8") as f:
+        f.write(json.dumps(schema) + "\n")
+    os.replace(tmp, out)
+
+
+def watch(fname: Path, out: "str | None") -> None:
+    """Regenerate the schema every time fname changes, until interrupted"""
+    last_mtime = None
+    changed_at = None
+    while True:
+        try:
+            mtime = fname.stat().st_mtime_ns
+        except FileNotFoundError:
+            mtime = None  # some editors save by replacing the file
+        if mtime is not None and mtime != last_mtime:
+            last_mtime = mtime
+            changed_at = time.monotonic()
+        now = time.monotonic()
+        if changed_at is not None and now - changed_at >= WATCH_DEBOUNCE:
+            changed_at = None
+            try:
+                write_schema(extract_file(fname), out)
+            except Exception as e:  # keep watching, the next save may fix it
+                print(f"error: {fname}: {e!r}", file=sys.stderr, flush=True)
+        time.sleep(WATCH_POLL_INTERVAL)
+
+
+def main(argv: "list[str] | None" = None) -> None:
+    parser = argparse.ArgumentParser(
+        description="Print the OpenAPI schema of a predictor without running it."
+    )
+    parser.add_argument(
+        "file", nargs="?", help="Predictor source file. Reads stdin if not given"
+    )
+    parser.add_argument("--out", help="Write the schema to this file")
+    parser.add_argument(
+        "--watch",
+        action="store_true",
+        help="Regenerate the schema every time the file changes",
+    )
+    args = parser.parse_args(argv)
+
+    if args.file is None:
+        if args.watch:
+            parser.error("--watch needs a file")
+        write_schema(extract_info(sys.stdin.read()), args.out)
+        return
+
+    p = Path(args.file)
+    if args.watch:
+        try:
+            watch(p, args.out)
+        except KeyboardInterrupt:
+            pass
+    elif p.exists():
+        write_schema(extract_file(p), args.out)
+
+
 if __name__ == "__main__":
-    if len(sys.argv) > 1:
-        p = Path(sys.argv[1])
-        if p.exists():
-            print(json.dumps(extract_file(p)))
-    else:
-        print(json.dumps(extract_info(sys.stdin.read())))
+    main()
@@ -2,11 +2,15 @@
 python -m cog.command.specification
 
 This prints a JSON object describing the inputs of the model.
+Pass `--format yaml` to print it as YAML instead.
 """
 
+import argparse
 import json
 from typing import Any, Dict, List, Union
 
+import yaml
+
 from ..errors import CogError, ConfigDoesNotExist, PredictorNotSet
 from ..predictor import load_config
 from ..schema import Status
@@ -33,7 +37,25 @@ def remove_title_next_to_ref(
     return schema_node
 
 
+def format_schema(schema: Dict[str, Any], output_format: str = "json") -> str:
+    if output_format == "yaml":
+        return yaml.safe_dump(schema, sort_keys=False)
+    return json.dumps(schema, indent=2)
+
+
 if __name__ == "__main__":
+    parser = argparse.ArgumentParser(
+        description="Print the OpenAPI schema of the model."
+    )
+    parser.add_argument(
+        "--format",
+        dest="output_format",
+        choices=["json", "yaml"],
+        default="json",
+        help="Format to print the schema in",
+    )
+    args = parser.parse_args()
+
     schema = {}
     try:
         with suppress_output():
@@ -49,4 +71,4 @@ if __name__ == "__main__":
         raise ConfigDoesNotExist("no cog.yaml found or present") from None
     except PredictorNotSet:
         raise PredictorNotSet("no predict method found in Predictor") from None
-    print(json.dumps(schema, indent=2))
+    print(format_schema(schema, args.output_format))
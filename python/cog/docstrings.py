@@ -0,0 +1,90 @@
+import inspect
+import re
+from typing import Dict, List, Optional
+
+# Section headers that introduce a function's parameters.
+PARAMETER_SECTIONS = ("args", "arguments", "parameters", "params")
+
+# A Google style parameter, e.g. "image (Path): The input image."
+GOOGLE_PARAMETER = re.compile(r"^\*{0,2}(\w+)\s*(?:\(.*\))?\s*:\s*(.*)$")
+
+
+def parse_parameter_descriptions(docstring: Optional[str]) -> Dict[str, str]:
+    """
+    Returns the descriptions of the parameters documented in a Google or NumPy
+    style docstring, by parameter name.
+    """
+    if not docstring:
+        return {}
+
+    lines = inspect.cleandoc(docstring).splitlines()
+    for i, line in enumerate(lines):
+        header = line.strip().lower()
+        if header.endswith(":") and header[:-1] in PARAMETER_SECTIONS:
+            return _parse_google_section(lines[i + 1 :])
+        if (
+            header in PARAMETER_SECTIONS
+            and i + 1 < len(lines)
+            and _is_underline(lines[i + 1])
+        ):
+            return _parse_numpy_section(lines[i + 2 :])
+    return {}
+
+
+def _parse_google_section(lines: List[str]) -> Dict[str, str]:
+    descriptions: Dict[str, List[str]] = {}
+    parameter_indent = None
+    name = None
+    for line in lines:
+        if not line.strip():
+            continue
+        indent = _indent(line)
+        if parameter_indent is None:
+            parameter_indent = indent
+        if indent < parameter_indent:
+            # The next section
+            break
+        if indent == parameter_indent:
+            match = GOOGLE_PARAMETER.match(line.strip())
+            if not match:
+                break
+            name = match.group(1)
+            descriptions[name] = [match.group(2)]
+        elif name is not None:
+            descriptions[name].append(line.strip())
+    return _join(descriptions)
+
+
+def _parse_numpy_section(lines: List[str]) -> Dict[str, str]:
+    descriptions: Dict[str, List[str]] = {}
+    name = None
+    for i, line in enumerate(lines):
+        if not line.strip():
+            continue
+        if _indent(line) == 0:
+            if i + 1 < len(lines) and _is_underline(lines[i + 1]):
+                # The next section
+                break
+            # e.g. "image : Path"
+            name = line.split(":", 1)[0].strip().lstrip("*")
+            descriptions[name] = []
+        elif name is not None:
+            descriptions[name].append(line.strip())
+    return _join(descriptions)
+
+
+def _join(descriptions: Dict[str, List[str]]) -> Dict[str, str]:
+    joined = {}
+    for name, parts in descriptions.items():
+        description = " ".join(part for part in parts if part)
+        if description:
+            joined[name] = description
+    return joined
+
+
+def _indent(line: str) -> int:
+    return len(line) - len(line.lstrip())
+
+
+def _is_underline(line: str) -> bool:
+    return bool(line.strip()) and set(line.strip()) == {"-"}
@@ -8,6 +8,19 @@ from urllib.parse import urlparse
 import requests
 
 
+def temp_dir() -> Optional[str]:
+    """
+    Returns the directory to put temporary files in, which is COG_TMPDIR if
+    it's set, creating it if needed. Otherwise returns None, so the tempfile
+    module uses its default.
+    """
+    path = os.environ.get("COG_TMPDIR")
+    if not path:
+        return None
+    os.makedirs(path, exist_ok=True)
+    return path
+
+
 def upload_file(fh: io.IOBase, output_file_prefix: str = None) -> str:
     fh.seek(0)
 
@@ -23,14 +36,24 @@ def upload_file(fh: io.IOBase, output_file_prefix: str = None) -> str:
     if isinstance(b, str):
         b = b.encode("utf-8")
     encoded_body = base64.b64encode(b)
+    mime_type = guess_content_type(fh) or "application/octet-stream"
+    s = encoded_body.decode("utf-8")
+    return f"data:{mime_type};base64,{s}"
+
+
+def guess_content_type(fh: io.IOBase) -> Optional[str]:
+    """
+    Returns the content type a file was explicitly given, or tries to guess it
+    from the file's name.
+    """
+    content_type = getattr(fh, "content_type", None)
+    if content_type is not None:
+        return content_type
     if getattr(fh, "name", None):
         # despite doing a getattr check here, pyright complains that io.IOBase has no attribute name
         # TODO: switch to typing.IO[]?
-        mime_type = mimetypes.guess_type(fh.name)[0]  # type: ignore
-    else:
-        mime_type = "application/octet-stream"
-    s = encoded_body.decode("utf-8")
-    return f"data:{mime_type};base64,{s}"
+        return mimetypes.guess_type(fh.name)[0]  # type: ignore
+    return None
 
 
 def guess_filename(obj: io.IOBase) -> str:
@@ -45,7 +68,7 @@ def put_file_to_signed_endpoint(
     fh.seek(0)
 
     filename = guess_filename(fh)
-    content_type, _ = mimetypes.guess_type(filename)
+    content_type = guess_content_type(fh)
 
     # set connect timeout to slightly more than a multiple of 3 to avoid
     # aligning perfectly with TCP retransmission timer
@@ -1,4 +1,7 @@
 import io
+import os
+import shutil
+import tempfile
 from datetime import datetime
 from enum import Enum
 from types import GeneratorType
@@ -6,6 +9,7 @@ from typing import Any, Callable
 
 from pydantic import BaseModel
 
+from .files import temp_dir
 from .types import Path
 
 
@@ -45,15 +49,31 @@ def upload_files(obj: Any, upload_file: Callable[[io.IOBase], str]) -> Any:
     """
     Iterates through an object from make_encodeable and uploads any files.
 
-    When a file is encountered, it will be passed to upload_file. Any paths will be opened and converted to files.
+    When a file is encountered, it will be passed to upload_file. Any paths will be opened and converted to files, and directories will be zipped.
     """
     if isinstance(obj, dict):
         return {key: upload_files(value, upload_file) for key, value in obj.items()}
     if isinstance(obj, list):
         return [upload_files(value, upload_file) for value in obj]
     if isinstance(obj, Path):
+        if obj.is_dir():
+            return _upload_directory(obj, upload_file)
         with obj.open("rb") as f:
+            content_type = getattr(obj, "content_type", None)
+            if content_type is not None:
+                f.content_type = content_type  # type: ignore
             return upload_file(f)
     if isinstance(obj, io.IOBase):
         return upload_file(obj)
     return obj
+
+
+def _upload_directory(path: Path, upload_file: Callable[[io.IOBase], str]) -> str:
+    """
+    Zips a directory into a temporary archive named after it, and uploads that.
+    """
+    with tempfile.TemporaryDirectory(dir=temp_dir()) as tmpdir:
+        base_name = os.path.join(tmpdir, path.name or "output")
+        archive = shutil.make_archive(base_name, "zip", root_dir=path)
+        with open(archive, "rb") as f:
+            return upload_file(f)
@@ -4,6 +4,9 @@ import os
 import structlog
 from structlog.typing import EventDict
 
+# The values LOG_FORMAT can be set to. "json" is the default.
+LOG_FORMATS = ("json", "development", "logfmt")
+
 
 def replace_level_with_severity(
     _: logging.Logger, __: str, event_dict: EventDict
@@ -24,8 +27,13 @@ def setup_logging(*, log_level: int = logging.NOTSET) -> None:
     """
 
     # Switch to human-friendly log output if LOG_FORMAT environment variable is
-    # set to "development".
-    development_logs = os.environ.get("LOG_FORMAT", "") == "development"
+    # set to "development", or to key=value pairs if it's set to "logfmt".
+    log_format = os.environ.get("LOG_FORMAT") or "json"
+    unknown_log_format = log_format not in LOG_FORMATS
+    if unknown_log_format:
+        log_format = "json"
+    development_logs = log_format == "development"
+    logfmt_logs = log_format == "logfmt"
 
     processors: list[structlog.types.Processor] = [
         structlog.contextvars.merge_contextvars,
@@ -45,12 +53,18 @@ def setup_logging(*, log_level: int = logging.NOTSET) -> None:
         # Outside of development mode `exc_info` must be set explicitly when
         # needed, and is translated into a formatted `exception` field.
         processors.append(structlog.processors.format_exc_info)
-        # Set `severity`, not `level`, for compatibility with Google
-        # Stackdriver logging expectations.
-        processors.append(replace_level_with_severity)
 
-    # Stackdriver logging expects a "message" field, not "event"
-    processors.append(structlog.processors.EventRenamer("message"))
+    if logfmt_logs:
+        # Use the field names logfmt consumers conventionally expect.
+        processors.append(structlog.processors.EventRenamer("msg"))
+    else:
+        if not development_logs:
+            # Set `severity`, not `level`, for compatibility with Google
+            # Stackdriver logging expectations.
+            processors.append(replace_level_with_severity)
+
+        # Stackdriver logging expects a "message" field, not "event"
+        processors.append(structlog.processors.EventRenamer("message"))
 
     structlog.configure(
         processors=processors
@@ -61,6 +75,10 @@ def setup_logging(*, log_level: int = logging.NOTSET) -> None:
 
     if development_logs:
         log_renderer = structlog.dev.ConsoleRenderer(event_key="message")  # type: ignore
+    elif logfmt_logs:
+        log_renderer = structlog.processors.LogfmtRenderer(  # type: ignore
+            key_order=["level", "msg"], drop_missing=True
+        )
     else:
         log_renderer = structlog.processors.JSONRenderer()  # type: ignore
 
@@ -87,3 +105,10 @@ def setup_logging(*, log_level: int = logging.NOTSET) -> None:
     # Reconfigure log levels for some overly chatty libraries
     logging.getLogger("uvicorn.access").setLevel(logging.WARNING)
     logging.getLogger("urllib3.connectionpool").setLevel(logging.ERROR)
+
+    if unknown_log_format:
+        structlog.get_logger("cog.logging").warning(
+            "unknown LOG_FORMAT, using json",
+            log_format=os.environ["LOG_FORMAT"],
+            supported=LOG_FORMATS,
+        )
@@ -1,13 +1,15 @@
+import copy
 import enum
 import importlib.util
 import inspect
 import io
 import os.path
 import sys
+import traceback
 import types
 import uuid
 from abc import ABC, abstractmethod
-from collections.abc import Iterator
+from collections.abc import AsyncIterator, Iterator
 from pathlib import Path
 from typing import (
     Any,
@@ -15,10 +17,10 @@ from typing import (
     Dict,
     List,
     Optional,
+    Set,
     Type,
     Union,
     cast,
-    get_type_hints,
 )
 from unittest.mock import patch
 
@@ -32,14 +34,16 @@ except ImportError:  # Python < 3.8
     from typing_compat import get_args, get_origin  # type: ignore
 
 import yaml
-from pydantic import BaseModel, Field, create_model
-from pydantic.fields import FieldInfo
+from pydantic import BaseModel, Field, ValidationError, create_model, validator
+from pydantic.fields import SHAPE_SET, FieldInfo, ModelField
 
 # Added in Python 3.9. Can be from typing if we drop support for <3.9
-from typing_extensions import Annotated
+from typing_extensions import Annotated, get_type_hints
 
+from .docstrings import parse_parameter_descriptions
 from .errors import ConfigDoesNotExist, PredictorNotSet
 from .types import (
+    Base64Bytes,
     CogConfig,
     Input,
     URLPath,
@@ -54,11 +58,29 @@ from .types import Secret as CogSecret
 
 log = structlog.get_logger("cog.server.predictor")
 
+# Generic input types (Optional, Union, List) can only be nested this deep,
+# e.g. Optional[List[Path]].
+MAX_INPUT_TYPE_DEPTH = 2
+
+# Predictor modules can set this to an example return value of predict(),
+# which is included in the Output schema.
+OUTPUT_EXAMPLE_NAME = "COG_OUTPUT_EXAMPLE"
+
+NoneType = type(None)
+
+# Set inputs are deduplicated by value, which only makes sense for these.
+ALLOWED_SET_ITEM_TYPES: List[Type[Any]] = [str, int, float, bool]
+
+# Unions of these types are checked strictly, as their values can't be told
+# apart once Pydantic has converted them.
+STRICT_UNION_TYPES: List[Type[Any]] = [str, int, float, bool]
+
 ALLOWED_INPUT_TYPES: List[Type[Any]] = [
     str,
     int,
     float,
     bool,
+    bytes,
     CogFile,
     CogPath,
     CogSecret,
@@ -72,6 +94,12 @@ class BasePredictor(ABC):
         """
         return
 
+    def warmup(self) -> None:
+        """
+        An optional method to run a first prediction before the model is ready.
+        """
+        return
+
     @abstractmethod
     def predict(self, **kwargs: Any) -> Any:
         """
@@ -125,6 +153,20 @@ def run_setup(predictor: BasePredictor) -> None:
     predictor.setup(weights=weights)
 
 
+def run_warmup(predictor: BasePredictor) -> None:
+    """
+    Runs the predictor's warmup(). If it fails, the model is still usable, so
+    the error is only logged, unless COG_WARMUP_FAILURE_FATAL is set.
+    """
+    try:
+        predictor.warmup()
+    except Exception:
+        if os.environ.get("COG_WARMUP_FAILURE_FATAL", "").lower() in ("1", "true"):
+            raise
+        print("Warning: warmup failed, continuing anyway", file=sys.stderr)
+        traceback.print_exc()
+
+
 def get_weights_type(setup_function: Callable[[Any], None]) -> Optional[Any]:
     signature = inspect.signature(setup_function)
     if "weights" not in signature.parameters:
@@ -260,6 +302,64 @@ class BaseInput(BaseModel):
         # When using `choices`, the type is converted into an enum to validate
         # But, after validation, we want to pass the actual value to predict(), not the enum object
         use_enum_values = True
+        # Without this, Pydantic would use the first member of a Union that
+        # the value can be converted to, e.g. turning 1 into "1" for a
+        # Union[str, int], rather than the member that it already is.
+        smart_union = True
+
+        @staticmethod
+        def schema_extra(schema: Dict[str, Any], model: Type["BaseInput"]) -> None:
+            # Pydantic doesn't mark fields that accept None as nullable, so we
+            # do it ourselves based on the declared type annotations.
+            properties = schema.get("properties", {})
+            for name, annotation in model.__annotations__.items():
+                if name not in properties:
+                    continue
+                if is_optional_type(annotation):
+                    properties[name]["nullable"] = True
+                    annotation = unwrap_optional_type(annotation)
+                items = properties[name].get("items")
+                if items and get_origin(annotation) in (List, list):
+                    if any(is_optional_type(a) for a in get_args(annotation)):
+                        items["nullable"] = True
+            # Defaults make sensible examples for user interfaces to prefill,
+            # except for secrets, which shouldn't be shown.
+            for prop in properties.values():
+                if "default" not in prop or "example" in prop:
+                    continue
+                if prop.get("x-cog-secret"):
+                    continue
+                prop["example"] = prop["default"]
+
+    @validator("*", pre=True)
+    def check_unique_items(cls, value: Any, field: ModelField) -> Any:
+        # Pydantic would silently drop duplicates when converting a list to a
+        # set, so reject them instead.
+        if field.shape == SHAPE_SET and isinstance(value, list):
+            try:
+                unique = len(set(value)) == len(value)
+            except TypeError:
+                return value  # unhashable items fail validation anyway
+            if not unique:
+                raise ValueError("items must be unique")
+        return value
+
+    @validator("*", pre=True)
+    def check_union_member(cls, value: Any, field: ModelField) -> Any:
+        # Pydantic converts values between the members of a Union, like the
+        # bool True to 1 for a Union[str, int], so check that unions of
+        # primitive types get a value that's already one of their members.
+        if value is None or not is_union_type(field.outer_type_):
+            return value
+        members = [t for t in get_args(field.outer_type_) if t is not NoneType]
+        # An Optional of a single type isn't a choice between types, so its
+        # values are still converted, like "3" to 3 for an Optional[int].
+        if len(members) < 2 or not all(t in STRICT_UNION_TYPES for t in members):
+            return value
+        if not any(_is_instance_of_primitive(value, t) for t in members):
+            accepted = ", ".join(t.__name__ for t in members)
+            raise ValueError(f"value must be one of these types: {accepted}")
+        return value
 
     def cleanup(self) -> None:
         """
@@ -274,27 +374,71 @@ class BaseInput(BaseModel):
                 value.unlink(missing_ok=True)
 
 
-def validate_input_type(type: Type[Any], name: str) -> None:
+def validate_input_type(type: Type[Any], name: str, depth: int = 0) -> None:
     if type is inspect.Signature.empty:
         raise TypeError(
             f"No input type provided for parameter `{name}`. Supported input types are: {readable_types_list(ALLOWED_INPUT_TYPES)}, or a Union or List of those types."
         )
     elif type not in ALLOWED_INPUT_TYPES:
-        if get_origin(type) in (Union, List, list) or (
-            hasattr(types, "UnionType") and get_origin(type) is types.UnionType
-        ):  # noqa: E721
+        if get_origin(type) in (Set, set):
+            for t in get_args(type):
+                if t not in ALLOWED_SET_ITEM_TYPES:
+                    raise TypeError(
+                        f"Unsupported input type {human_readable_type_name(type)} for parameter `{name}`. Sets can only contain {readable_types_list(ALLOWED_SET_ITEM_TYPES)}."
+                    )
+        elif is_union_type(type) or get_origin(type) in (List, list):
+            if depth >= MAX_INPUT_TYPE_DEPTH:
+                raise TypeError(
+                    f"Unsupported input type for parameter `{name}`: {type} is nested too deeply. Union, Optional and List types can only be nested {MAX_INPUT_TYPE_DEPTH} levels deep."
+                )
             for t in get_args(type):
-                validate_input_type(t, name)
+                # None is only allowed as a member of a Union, i.e. Optional
+                if t is NoneType and is_union_type(type):
+                    continue
+                validate_input_type(t, name, depth + 1)
         else:
             raise TypeError(
                 f"Unsupported input type {human_readable_type_name(type)} for parameter `{name}`. Supported input types are: {readable_types_list(ALLOWED_INPUT_TYPES)}, or a Union or List of those types."
             )
 
 
+def _is_instance_of_primitive(value: Any, t: Type[Any]) -> bool:
+    # bool is a subclass of int, but JSON booleans aren't numbers.
+    if isinstance(value, bool):
+        return t is bool
+    if t is float:
+        return isinstance(value, (int, float))
+    return isinstance(value, t)
+
+
+def is_union_type(t: Any) -> bool:
+    return get_origin(t) is Union or (
+        hasattr(types, "UnionType") and get_origin(t) is types.UnionType
+    )
+
+
+def is_optional_type(t: Any) -> bool:
+    return is_union_type(t) and NoneType in get_args(t)
+
+
+def unwrap_optional_type(t: Any) -> Any:
+    """
+    Returns the type wrapped by an Optional, e.g. List[Path] for
+    Optional[List[Path]]. Unions of several other types are left as a Union.
+    """
+    args = tuple(a for a in get_args(t) if a is not NoneType)
+    if len(args) == 1:
+        return args[0]
+    return Union[args]  # type: ignore
+
+
 def get_input_create_model_kwargs(
-    signature: inspect.Signature, input_types: Dict[str, Any]
+    signature: inspect.Signature,
+    input_types: Dict[str, Any],
+    descriptions: Optional[Dict[str, str]] = None,
 ) -> Dict[str, Any]:
     create_model_kwargs = {}
+    descriptions = descriptions or {}
 
     order = 0
 
@@ -304,10 +448,30 @@ def get_input_create_model_kwargs(
 
         InputType = input_types[name]
 
+        # Inputs can also be declared as Annotated[T, Input(...)], in which
+        # case a plain default is the input's default.
+        annotated_input = None
+        if hasattr(InputType, "__metadata__"):
+            annotated_input = next(
+                (m for m in InputType.__metadata__ if isinstance(m, FieldInfo)),
+                None,
+            )
+            InputType = InputType.__origin__
+
         validate_input_type(InputType, name)
 
+        if annotated_input is not None:
+            if isinstance(parameter.default, FieldInfo):
+                raise TypeError(
+                    f"The input {name} has an Input() in both its annotation and its default. Use one or the other."
+                )
+            # Copied, because the annotation is shared between calls.
+            default = copy.copy(annotated_input)
+            default.extra = dict(default.extra)
+            if parameter.default is not inspect.Signature.empty:
+                default.default = parameter.default
         # if no default is specified, create an empty, required input
-        if parameter.default is inspect.Signature.empty:
+        elif parameter.default is inspect.Signature.empty:
             default = Input()
         else:
             default = parameter.default
@@ -315,16 +479,20 @@ def get_input_create_model_kwargs(
             if not isinstance(default, FieldInfo):
                 default = Input(default=default)
 
+        # Fall back to the parameter's description in the docstring, if the
+        # predictor documents it there instead.
+        if default.description is None and name in descriptions:
+            default.description = descriptions[name]
+
         # Fields aren't ordered, so use this pattern to ensure defined order
         # https://github.com/go-openapi/spec/pull/116
         default.extra["x-order"] = order
         order += 1
 
         # Choices!
-        if default.extra.get("choices"):
-            choices = default.extra["choices"]
-            # It will be passed automatically as 'enum' in the schema, so remove it as an extra field.
-            del default.extra["choices"]
+        # It will be passed automatically as 'enum' in the schema, so remove it as an extra field.
+        choices = default.extra.pop("choices", None)
+        if choices:
             if InputType == str:  # noqa: E721
 
                 class StringEnum(str, enum.Enum):
@@ -340,11 +508,46 @@ def get_input_create_model_kwargs(
                     f"The input {name} uses the option choices. Choices can only be used with str or int types."
                 )
 
+        # Pydantic would encode a string as UTF-8 for a bytes field, but bytes
+        # inputs are sent as base64.
+        if InputType is bytes:
+            InputType = Base64Bytes
+
+        # Pydantic only applies min_length and max_length to strings, so for
+        # lists and sets they become constraints on the number of items.
+        ListType = (
+            unwrap_optional_type(InputType)
+            if is_optional_type(InputType)
+            else InputType
+        )
+        if get_origin(ListType) in (List, list, Set, set):
+            if default.min_length is not None:
+                default.min_items, default.min_length = default.min_length, None
+            if default.max_length is not None:
+                default.max_items, default.max_length = default.max_length, None
+        elif default.min_items is not None or default.max_items is not None:
+            raise TypeError(
+                f"The input {name} uses min_items or max_items, which can only be used with list types."
+            )
+
         create_model_kwargs[name] = (InputType, default)
 
     return create_model_kwargs
 
 
+def get_deprecation_warnings(input: Any) -> List[str]:
+    """
+    Returns a warning for each deprecated input that was given a value.
+    """
+    if not isinstance(input, BaseModel):
+        return []
+    return [
+        f"input '{name}' is deprecated"
+        for name, field in input.__fields__.items()
+        if name in input.__fields_set__ and field.field_info.extra.get("deprecated")
+    ]
+
+
 def get_predict(predictor: Any) -> Callable[..., Any]:
     if hasattr(predictor, "predict"):
         return predictor.predict
@@ -368,7 +571,9 @@ def get_input_type(predictor: BasePredictor) -> Type[BaseInput]:
     predict = get_predict(predictor)
     signature = inspect.signature(predict)
 
-    input_types = get_type_hints(predict)
+    # Look through any decorators on predict(), so that annotations are
+    # resolved in the module where it was defined.
+    input_types = get_type_hints(inspect.unwrap(predict), include_extras=True)
     if "return" in input_types:
         del input_types["return"]
 
@@ -378,7 +583,11 @@ def get_input_type(predictor: BasePredictor) -> Type[BaseInput]:
         __base__=BaseInput,
         __module__=__name__,
         __validators__=None,
-        **get_input_create_model_kwargs(signature, input_types),
+        **get_input_create_model_kwargs(
+            signature,
+            input_types,
+            parse_parameter_descriptions(inspect.getdoc(predict)),
+        ),
     )  # type: ignore
 
 
@@ -389,7 +598,7 @@ def get_output_type(predictor: BasePredictor) -> Type[BaseModel]:
 
     predict = get_predict(predictor)
 
-    input_types = get_type_hints(predict)
+    input_types = get_type_hints(inspect.unwrap(predict))
 
     OutputType = input_types.pop("return", None)
     if OutputType is None:
@@ -409,7 +618,7 @@ For example:
         )
 
     # The type that goes in the response is a list of the yielded type
-    if get_origin(OutputType) is Iterator:
+    if get_origin(OutputType) in (Iterator, AsyncIterator):
         # Annotated allows us to attach Field annotations to the list, which we use to mark that this is an iterator
         # https://pydantic-docs.helpmanual.io/usage/schema/#typingannotated-fields
         field = Field(**{"x-cog-array-type": "iterator"})  # type: ignore
@@ -418,7 +627,7 @@ For example:
     name = OutputType.__name__ if hasattr(OutputType, "__name__") else ""
 
     if name == "Output":
-        return OutputType
+        return with_output_example(OutputType, get_output_example(predict))
 
     # We wrap the OutputType in an Output class to
     # ensure consistent naming of the interface in the schema.
@@ -439,18 +648,54 @@ For example:
         class Output(OutputType):  # type: ignore
             pass
 
-        return Output
     else:
 
         class Output(BaseModel):
             __root__: OutputType  # type: ignore
 
-        return Output
+    return with_output_example(Output, get_output_example(predict))
+
+
+def get_output_example(predict: Callable[..., Any]) -> Any:
+    """
+    Returns the example output declared with a module-level COG_OUTPUT_EXAMPLE
+    in the predictor's module, or None if there isn't one.
+    """
+    return getattr(inspect.unwrap(predict), "__globals__", {}).get(OUTPUT_EXAMPLE_NAME)
+
+
+def with_output_example(
+    OutputType: Type[BaseModel], example: Any
+) -> Type[BaseModel]:
+    """
+    Validates an example output against the Output model and attaches it to
+    the model's schema as `example`.
+    """
+    if example is None:
+        return OutputType
+
+    try:
+        OutputType.parse_obj(example)
+    except ValidationError as e:
+        raise TypeError(
+            f"{OUTPUT_EXAMPLE_NAME} does not match the output type of predict(): {e}"
+        ) from e
+
+    class Output(OutputType):  # type: ignore
+        class Config:
+            schema_extra = {"example": example}
+
+    return Output
 
 
 def get_train(predictor: Any) -> Callable[..., Any]:
     if hasattr(predictor, "train"):
         return predictor.train
+    if not inspect.isfunction(predictor):
+        raise TypeError(
+            "train mode requested but no train() found on "
+            f"{type(predictor).__name__}"
+        )
     return predictor
 
 
@@ -470,7 +715,7 @@ def get_training_input_type(predictor: BasePredictor) -> Type[BaseInput]:
     train = get_train(predictor)
     signature = inspect.signature(train)
 
-    input_types = get_type_hints(train)
+    input_types = get_type_hints(inspect.unwrap(train), include_extras=True)
     if "return" in input_types:
         del input_types["return"]
 
@@ -480,7 +725,11 @@ def get_training_input_type(predictor: BasePredictor) -> Type[BaseInput]:
         __base__=BaseInput,
         __module__=__name__,
         __validators__=None,
-        **get_input_create_model_kwargs(signature, input_types),
+        **get_input_create_model_kwargs(
+            signature,
+            input_types,
+            parse_parameter_descriptions(inspect.getdoc(train)),
+        ),
     )  # type: ignore
 
 
@@ -491,7 +740,7 @@ def get_training_output_type(predictor: BasePredictor) -> Type[BaseModel]:
 
     train = get_train(predictor)
 
-    input_types = get_type_hints(train)
+    input_types = get_type_hints(inspect.unwrap(train))
     TrainingOutputType = input_types.pop("return", None)
     if TrainingOutputType is None:
         raise TypeError(
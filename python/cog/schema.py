@@ -29,13 +29,17 @@ class WebhookEvent(str, Enum):
     OUTPUT = "output"
     LOGS = "logs"
     COMPLETED = "completed"
+    CANCELED = "canceled"
+    # Sent as soon as a prediction is accepted, before it starts processing.
+    # Clients have to ask for it in the filter, so it isn't a default event.
+    STARTING = "starting"
 
     @classmethod
     def default_events(cls) -> t.List["WebhookEvent"]:
         # if this is a set, it gets serialized to an array with an unstable ordering
         # so even though it's logically a set, have it as a list for deterministic schemas
         # note: this change removes "uniqueItems":true
-        return [cls.START, cls.OUTPUT, cls.LOGS, cls.COMPLETED]
+        return [cls.START, cls.OUTPUT, cls.LOGS, cls.COMPLETED, cls.CANCELED]
 
 
 class PredictionBaseModel(pydantic.BaseModel, extra=pydantic.Extra.allow):
@@ -76,10 +80,23 @@ class PredictionResponse(PredictionBaseModel):
 
     logs: str = ""
     error: t.Optional[str]
+    # The traceback of the exception that failed the prediction, if any
+    traceback: t.Optional[str]
+    # Non-fatal problems with the request, like using deprecated inputs
+    warnings: t.Optional[t.List[str]]
     status: t.Optional[Status]
+    cancel_reason: t.Optional[str]
 
     metrics: t.Optional[t.Dict[str, t.Any]]
 
+    # Headers the predictor set for the HTTP response of a synchronous
+    # prediction. They aren't part of the prediction, so they're private.
+    _response_headers: t.Dict[str, str] = pydantic.PrivateAttr(default_factory=dict)
+
+    @property
+    def response_headers(self) -> t.Dict[str, str]:
+        return self._response_headers
+
     @classmethod
     def with_types(cls, input_type: t.Type[t.Any], output_type: t.Type[t.Any]) -> t.Any:
         # [compat] Input is implicitly optional -- previous versions of the
@@ -93,6 +110,16 @@ class PredictionResponse(PredictionBaseModel):
         )
 
 
+class BatchPredictionRequest(pydantic.BaseModel):
+    # Inputs are validated one at a time, so that an invalid input fails its
+    # own prediction rather than the whole batch.
+    inputs: t.List[t.Optional[t.Dict[str, t.Any]]]
+
+
+class CancelRequest(pydantic.BaseModel):
+    reason: t.Optional[str]
+
+
 class TrainingRequest(PredictionRequest):
     pass
 
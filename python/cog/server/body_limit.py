@@ -0,0 +1,77 @@
+from typing import Dict, Tuple
+
+from fastapi.responses import JSONResponse
+from starlette.types import ASGIApp, Message, Receive, Scope, Send
+
+DEFAULT_MAX_BODY_SIZE = 100 * 1024 * 1024  # 100 MiB
+
+
+class BodySizeLimitMiddleware:
+    """
+    Rejects requests to the given path prefixes with a body larger than
+    max_body_size, before the body is parsed.
+    """
+
+    def __init__(
+        self, app: ASGIApp, max_body_size: int, paths: Tuple[str, ...]
+    ) -> None:
+        self.app = app
+        self.max_body_size = max_body_size
+        self.paths = paths
+
+    async def __call__(self, scope: Scope, receive: Receive, send: Send) -> None:
+        if scope["type"] != "http" or not scope["path"].startswith(self.paths):
+            await self.app(scope, receive, send)
+            return
+
+        headers: Dict[bytes, bytes] = dict(scope["headers"])
+        content_length = headers.get(b"content-length")
+        if content_length is not None:
+            # e.g. "abc", or a list of lengths from repeated headers
+            if not content_length.isdigit():
+                response = JSONResponse(
+                    {"detail": "Content-Length header is not valid"},
+                    status_code=400,
+                )
+                await response(scope, receive, send)
+                return
+            if int(content_length) > self.max_body_size:
+                await self._reject(scope, receive, send)
+                return
+            await self.app(scope, receive, send)
+            return
+
+        # Without a Content-Length (i.e. a chunked request), we have to read
+        # the body to find out how big it is. FastAPI reads the whole body
+        # before handling the request anyway, so buffer it and replay it.
+        body = b""
+        more_body = True
+        while more_body:
+            message = await receive()
+            if message["type"] != "http.request":
+                return
+            body += message.get("body", b"")
+            if len(body) > self.max_body_size:
+                await self._reject(scope, receive, send)
+                return
+            more_body = message.get("more_body", False)
+
+        replayed = False
+
+        async def replay() -> Message:
+            nonlocal replayed
+            if replayed:
+                return await receive()
+            replayed = True
+            return {"type": "http.request", "body": body, "more_body": False}
+
+        await self.app(scope, replay, send)
+
+    async def _reject(self, scope: Scope, receive: Receive, send: Send) -> None:
+        response = JSONResponse(
+            {
+                "detail": f"Request body is larger than the limit of {self.max_body_size} bytes",
+            },
+            status_code=413,
+        )
+        await response(scope, receive, send)
@@ -0,0 +1,62 @@
+from typing import Tuple
+
+from starlette.datastructures import Headers
+from starlette.middleware.gzip import GZipMiddleware, GZipResponder
+from starlette.types import ASGIApp, Message, Receive, Scope, Send
+
+# Responses that are sent a piece at a time as they're produced. Compressing
+# them would hold the pieces back until enough output had built up.
+STREAMING_MEDIA_TYPES = ("application/x-ndjson", "text/event-stream")
+
+
+class StreamingAwareGZipMiddleware(GZipMiddleware):
+    """
+    Like GZipMiddleware, but leaves responses with one of the given media
+    types uncompressed, so they're streamed to the client as they're sent.
+    """
+
+    def __init__(
+        self,
+        app: ASGIApp,
+        minimum_size: int = 500,
+        excluded_media_types: Tuple[str, ...] = STREAMING_MEDIA_TYPES,
+    ) -> None:
+        super().__init__(app, minimum_size=minimum_size)
+        self.excluded_media_types = excluded_media_types
+
+    async def __call__(self, scope: Scope, receive: Receive, send: Send) -> None:
+        if scope["type"] == "http":
+            headers = Headers(scope=scope)
+            if "gzip" in headers.get("Accept-Encoding", ""):
+                responder = _Responder(
+                    self.app,
+                    self.minimum_size,
+                    compresslevel=self.compresslevel,
+                    excluded_media_types=self.excluded_media_types,
+                )
+                await responder(scope, receive, send)
+                return
+        await self.app(scope, receive, send)
+
+
+class _Responder(GZipResponder):
+    def __init__(
+        self,
+        app: ASGIApp,
+        minimum_size: int,
+        compresslevel: int,
+        excluded_media_types: Tuple[str, ...],
+    ) -> None:
+        super().__init__(app, minimum_size, compresslevel=compresslevel)
+        self.excluded_media_types = excluded_media_types
+        self.excluded = False
+
+    async def send_with_gzip(self, message: Message) -> None:
+        if message["type"] == "http.response.start":
+            content_type = Headers(raw=message["headers"]).get("content-type", "")
+            media_type = content_type.split(";")[0].strip()
+            self.excluded = media_type in self.excluded_media_types
+        if self.excluded:
+            await self.send(message)
+            return
+        await super().send_with_gzip(message)
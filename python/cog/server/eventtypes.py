@@ -28,6 +28,24 @@ class PredictionOutput:
     payload: Any
 
 
+@define
+class SpilledPredictionOutput:
+    # A pickled PredictionOutput that was too large to send inline
+    path: str
+
+
+@define
+class PredictionMetric:
+    name: str
+    value: Any
+
+
+@define
+class PredictionResponseHeader:
+    name: str
+    value: str
+
+
 @define
 class PredictionOutputType:
     multi: bool = False
@@ -38,6 +56,7 @@ class Done:
     canceled: bool = False
     error: bool = False
     error_detail: str = ""
+    error_traceback: str = ""
 
 
 @define
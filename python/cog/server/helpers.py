@@ -1,9 +1,25 @@
+import codecs
 import io
 import os
 import selectors
 import threading
 import uuid
-from typing import Callable, Optional, Sequence, TextIO
+from typing import Callable, Dict, Optional, Sequence, TextIO, Tuple, Union
+
+# The stream being read on this thread, so that the error handler below can
+# count its replacements. Error handlers can't be unregistered, so one is
+# shared by every stream rather than registering one per stream.
+_reading = threading.local()
+
+
+def _replace_decode_error(error: UnicodeError) -> Tuple[Union[str, bytes], int]:
+    stream = getattr(_reading, "stream", None)
+    if stream is not None:
+        stream.decode_errors += 1
+    return codecs.replace_errors(error)
+
+
+codecs.register_error("cog-replace", _replace_decode_error)
 
 
 class WrappedStream:
@@ -12,6 +28,8 @@ class WrappedStream:
         self._stream = stream
         self._original_fp: Optional[TextIO] = None
         self._wrapped_fp: Optional[TextIO] = None
+        # How many invalid UTF-8 sequences read from the stream were replaced.
+        self.decode_errors = 0
 
     def wrap(self) -> None:
         r, w = os.pipe()
@@ -33,8 +51,12 @@ class WrappedStream:
         #
         # We set the FD to be non-blocking so that we can select/poll/epoll
         # over multiple wrapped streams.
+        #
+        # Native libraries sometimes write bytes that aren't valid UTF-8.
+        # Those are replaced, rather than losing the line or, worse, stopping
+        # the thread reading it.
         os.set_blocking(r, False)
-        self._wrapped_fp = os.fdopen(r, "r")
+        self._wrapped_fp = os.fdopen(r, "r", encoding="utf-8", errors="cog-replace")
 
     def write(self, data: str) -> int:
         return self._stream.write(data)
@@ -74,6 +96,7 @@ class StreamRedirector(threading.Thread):
         self.drain_token = uuid.uuid4().hex
         self.drain_event = threading.Event()
         self.terminate_token = uuid.uuid4().hex
+        self._reported_decode_errors: Dict[str, int] = {}
 
         if len(self._streams) == 0:
             raise ValueError("provide at least one wrapped stream to redirect")
@@ -118,6 +141,7 @@ class StreamRedirector(threading.Thread):
             for key, _ in selector.select():
                 stream = key.data
 
+                _reading.stream = stream
                 for line in stream.wrapped:
                     if not line.endswith("\n"):
                         # TODO: limit how much we're prepared to buffer on a
@@ -146,5 +170,21 @@ class StreamRedirector(threading.Thread):
                         self._write_hook(stream.name, stream.original, full_line + "\n")
 
                     if drain_tokens_seen >= drain_tokens_needed:
+                        self._report_decode_errors()
                         self.drain_event.set()
                         drain_tokens_seen = 0
+
+    def _report_decode_errors(self) -> None:
+        # Called when the streams are drained, at the end of setup and of each
+        # prediction, so the logs say where their replacement characters came
+        # from.
+        for stream in self._streams:
+            reported = self._reported_decode_errors.get(stream.name, 0)
+            if stream.decode_errors > reported:
+                self._write_hook(
+                    stream.name,
+                    stream.original,
+                    f"[cog] replaced {stream.decode_errors - reported} invalid "
+                    f"UTF-8 sequences in {stream.name}\n",
+                )
+                self._reported_decode_errors[stream.name] = stream.decode_errors
@@ -1,21 +1,30 @@
 import argparse
 import asyncio
 import functools
+import hmac
+import json
 import logging
 import os
+import platform
 import signal
 import socket
 import sys
 import textwrap
 import threading
+import time
 import traceback
+from collections import deque
 from datetime import datetime, timezone
 from enum import Enum, auto, unique
 from typing import (
     TYPE_CHECKING,
     Any,
+    AsyncIterator,
     Awaitable,
     Callable,
+    Deque,
+    Dict,
+    List,
     Optional,
     TypeVar,
 )
@@ -26,14 +35,14 @@ if TYPE_CHECKING:
 import attrs
 import structlog
 import uvicorn
-from fastapi import Body, FastAPI, Header, HTTPException, Path, Response
+from fastapi import Body, FastAPI, Header, HTTPException, Path, Request, Response
 from fastapi.encoders import jsonable_encoder
 from fastapi.exceptions import RequestValidationError
-from fastapi.responses import JSONResponse
+from fastapi.responses import JSONResponse, StreamingResponse
 from pydantic import ValidationError
 from pydantic.error_wrappers import ErrorWrapper
 
-from .. import schema
+from .. import __version__, schema
 from ..errors import PredictorNotSet
 from ..files import upload_file
 from ..json import upload_files
@@ -48,17 +57,33 @@ from ..predictor import (
     load_slim_predictor_from_ref,
 )
 from ..types import CogConfig
+from .body_limit import DEFAULT_MAX_BODY_SIZE, BodySizeLimitMiddleware
+from .compression import StreamingAwareGZipMiddleware
+from .metrics import CONTENT_TYPE as METRICS_CONTENT_TYPE
+from .multipart import MultipartPredictionMiddleware
 from .runner import (
+    SLOT_INDEX,
     PredictionRunner,
+    PredictionTask,
     RunnerBusyError,
     SetupResult,
     SetupTask,
     UnknownPredictionError,
 )
 from .telemetry import make_trace_context, trace_context
+from .webhook import wait_for_pending_webhooks
+from .worker import DEFAULT_MAX_INLINE_OUTPUT_SIZE
 
 log = structlog.get_logger("cog.server.http")
 
+# How often requests waiting in the queue check whether the runner is free.
+QUEUE_POLL_INTERVAL = 0.1
+
+# How often synchronous predictions check whether their client is still there.
+RESULT_POLL_INTERVAL = 0.1
+
+NDJSON_MEDIA_TYPE = "application/x-ndjson"
+
 
 @unique
 class Health(Enum):
@@ -73,6 +98,7 @@ class MyState:
     health: Health
     setup_task: Optional[SetupTask]
     setup_result: Optional[SetupResult]
+    runner: Optional[PredictionRunner]
 
 
 class MyFastAPI(FastAPI):
@@ -98,6 +124,10 @@ def add_setup_failed_routes(app: MyFastAPI, started_at: datetime, msg: str) -> N
         setup = attrs.asdict(app.state.setup_result)
         return jsonable_encoder({"status": app.state.health.name, "setup": setup})
 
+    @app.get("/health-check/live", include_in_schema=False)
+    async def liveness_startup_failed() -> Any:
+        return JSONResponse({"status": "DEAD"}, status_code=503)
+
 
 def create_app(
     config: CogConfig,
@@ -106,15 +136,53 @@ def create_app(
     upload_url: Optional[str] = None,
     mode: str = "predict",
     is_build: bool = False,
+    isolate_cwd: bool = False,
+    retry_after: int = 1,
+    failed_prediction_http_status: int = 200,
+    admin_token: Optional[str] = None,
+    max_inline_output_size: int = DEFAULT_MAX_INLINE_OUTPUT_SIZE,
+    max_worker_restarts: int = 0,
+    worker_restart_window: float = 300.0,
+    enable_metrics: bool = True,
+    gpu_wait_timeout: Optional[float] = None,
+    max_body_size: int = DEFAULT_MAX_BODY_SIZE,
+    queue_depth: int = 0,
+    queue_timeout: float = 30.0,
+    completed_prediction_ttl: float = 300.0,
+    max_completed_predictions: int = 100,
+    slot_release_timeout: float = 300.0,
+    gzip_min_size: Optional[int] = None,
+    recent_predictions: int = 20,
 ) -> MyFastAPI:
+    if failed_prediction_http_status not in (200, 500):
+        raise ValueError(
+            f"failed_prediction_http_status must be 200 or 500, not {failed_prediction_http_status}"
+        )
+
     app = MyFastAPI(
         title="Cog",  # TODO: mention model name?
         # version=None # TODO
     )
+    # Middleware added later wraps what's added before, so this only sees
+    # bodies that are within the size limit.
+    app.add_middleware(
+        MultipartPredictionMiddleware, paths=("/predictions", "/trainings")
+    )
+    app.add_middleware(
+        BodySizeLimitMiddleware,
+        max_body_size=max_body_size,
+        paths=("/predictions", "/trainings"),
+    )
+    if gzip_min_size is not None:
+        # Only responses at least this big are compressed, and only for
+        # clients that send `Accept-Encoding: gzip`. Streamed responses are
+        # left alone, as compressing them would hold back their output.
+        app.add_middleware(StreamingAwareGZipMiddleware, minimum_size=gzip_min_size)
 
     app.state.health = Health.STARTING
     app.state.setup_task = None
     app.state.setup_result = None
+    app.state.runner = None
     started_at = datetime.now(tz=timezone.utc)
 
     # shutdown is needed no matter what happens
@@ -125,6 +193,20 @@ def create_app(
             shutdown_event.set()
         return JSONResponse({}, status_code=200)
 
+    # Like shutdown, this doesn't depend on the predictor, so it's available
+    # before setup has finished, or even if it failed.
+    @app.get("/version", include_in_schema=False)
+    async def version() -> Any:
+        return JSONResponse(
+            {
+                "cog": __version__,
+                "python": platform.python_version(),
+                "python_implementation": platform.python_implementation(),
+            },
+            # It can only change when the server restarts.
+            headers={"Cache-Control": "max-age=300"},
+        )
+
     try:
         predictor_ref = get_predictor_ref(config, mode)
         predictor = load_slim_predictor_from_ref(predictor_ref, "predict")
@@ -135,11 +217,27 @@ def create_app(
         add_setup_failed_routes(app, started_at, msg)
         return app
 
+    def worker_restarted(setup_task: SetupTask) -> None:
+        # Predictions are unavailable again until the new worker is set up.
+        app.state.health = Health.STARTING
+        app.state.setup_task = setup_task
+
     runner = PredictionRunner(
         predictor_ref=predictor_ref,
         shutdown_event=shutdown_event,
         upload_url=upload_url,
+        isolate_cwd=isolate_cwd,
+        max_inline_output_size=max_inline_output_size,
+        max_worker_restarts=max_worker_restarts,
+        worker_restart_window=worker_restart_window,
+        on_worker_restart=worker_restarted,
+        gpu_wait_timeout=gpu_wait_timeout,
+        completed_prediction_ttl=completed_prediction_ttl,
+        max_completed_predictions=max_completed_predictions,
+        slot_release_timeout=slot_release_timeout,
+        recent_predictions=recent_predictions,
     )
+    app.state.runner = runner
 
     class PredictionRequest(schema.PredictionRequest.with_types(input_type=InputType)):
         pass
@@ -148,6 +246,9 @@ def create_app(
         input_type=InputType, output_type=OutputType
     )
 
+    # Only outputs that are yielded a piece at a time can be streamed.
+    output_is_iterator = OutputType.schema().get("x-cog-array-type") == "iterator"
+
     http_semaphore = asyncio.Semaphore(threads)
 
     if TYPE_CHECKING:
@@ -184,6 +285,7 @@ def create_app(
                 response_model_exclude_unset=True,
             )
             def train(
+                http_request: Request,
                 request: TrainingRequest = Body(default=None),
                 prefer: Optional[str] = Header(default=None),
                 traceparent: Optional[str] = Header(
@@ -194,7 +296,7 @@ def create_app(
                 ),
             ) -> Any:  # type: ignore
                 with trace_context(make_trace_context(traceparent, tracestate)):
-                    return predict(request, prefer)
+                    return predict(http_request, request, prefer)
 
             @app.put(
                 "/trainings/{training_id}",
@@ -202,6 +304,7 @@ def create_app(
                 response_model_exclude_unset=True,
             )
             def train_idempotent(
+                http_request: Request,
                 training_id: str = Path(..., title="Training ID"),
                 request: TrainingRequest = Body(..., title="Training Request"),
                 prefer: Optional[str] = Header(default=None),
@@ -213,13 +316,15 @@ def create_app(
                 ),
             ) -> Any:
                 with trace_context(make_trace_context(traceparent, tracestate)):
-                    return predict_idempotent(training_id, request, prefer)
+                    return predict_idempotent(
+                        http_request, training_id, request, prefer
+                    )
 
             @app.post("/trainings/{training_id}/cancel")
             def cancel_training(
                 training_id: str = Path(..., title="Training ID"),
             ) -> Any:
-                return cancel(training_id)
+                return cancel(training_id, request=None)
 
         except Exception as e:
             if isinstance(e, (PredictorNotSet, FileNotFoundError)) and not is_build:
@@ -263,7 +368,63 @@ def create_app(
         else:
             health = app.state.health
         setup = attrs.asdict(app.state.setup_result) if app.state.setup_result else {}
-        return jsonable_encoder({"status": health.name, "setup": setup})
+        slots = runner.slot_stats()
+        return jsonable_encoder(
+            {
+                "status": health.name,
+                "setup": setup,
+                "slots": attrs.asdict(slots),
+                # How many predictions can run at once, and how many are.
+                "concurrency": {"max": slots.total, "current": slots.busy},
+                "worker_rss_bytes": runner.worker_rss_bytes(),
+            }
+        )
+
+    # Unlike /health-check, which reports whether predictions can run, this
+    # only reports whether the server is working towards that. It stays
+    # healthy while a long setup runs, so it's safe to use as a liveness probe.
+    @app.get("/health-check/live", include_in_schema=False)
+    async def liveness() -> Any:
+        _check_setup_result()
+        if app.state.health == Health.SETUP_FAILED or runner.slot_stats().defunct:
+            return JSONResponse({"status": "DEAD"}, status_code=503)
+        return JSONResponse({"status": "ALIVE"}, status_code=200)
+
+    if enable_metrics:
+
+        @app.get("/metrics", include_in_schema=False)
+        async def metrics() -> Any:
+            """
+            Prediction metrics in the Prometheus text format
+            """
+            busy_slots = 0 if runner.current_prediction() is None else 1
+            return Response(
+                runner.metrics.render(busy_slots=busy_slots),
+                media_type=METRICS_CONTENT_TYPE,
+            )
+
+    @app.get("/setup-logs", include_in_schema=False)
+    async def setup_logs() -> Any:
+        """
+        Stream the logs from setup as they're written, until setup finishes
+        """
+
+        async def stream() -> AsyncIterator[str]:
+            sent = 0
+            while True:
+                _check_setup_result()
+                if app.state.setup_task is None and app.state.setup_result:
+                    # Finish with whatever the final result has that we
+                    # haven't sent yet, so the stream matches it exactly.
+                    yield app.state.setup_result.logs[sent:]
+                    return
+                logs = runner.setup_logs()
+                if len(logs) > sent:
+                    yield logs[sent:]
+                    sent = len(logs)
+                await asyncio.sleep(0.1)
+
+        return StreamingResponse(stream(), media_type="text/plain")
 
     @limited
     @app.post(
@@ -272,6 +433,7 @@ def create_app(
         response_model_exclude_unset=True,
     )
     async def predict(
+        http_request: Request,
         request: PredictionRequest = Body(default=None),
         prefer: Optional[str] = Header(default=None),
         traceparent: Optional[str] = Header(default=None, include_in_schema=False),
@@ -280,18 +442,22 @@ def create_app(
         """
         Run a single prediction on the model
         """
-        if runner.is_busy():
-            return JSONResponse(
-                {"detail": "Already running a prediction"}, status_code=409
-            )
+        if _shutting_down():
+            return _unavailable_response()
+        if not await _wait_for_slot(http_request, prediction_id=None):
+            return _unavailable_response()
+        # Shutdown may have started while the request was queued.
+        if _shutting_down():
+            return _unavailable_response()
 
         # TODO: spec-compliant parsing of Prefer header.
         respond_async = prefer == "respond-async"
 
         with trace_context(make_trace_context(traceparent, tracestate)):
-            return _predict(
+            return await _predict(
                 request=request,
                 respond_async=respond_async,
+                http_request=http_request,
             )
 
     @limited
@@ -301,6 +467,7 @@ def create_app(
         response_model_exclude_unset=True,
     )
     async def predict_idempotent(
+        http_request: Request,
         prediction_id: str = Path(..., title="Prediction ID"),
         request: PredictionRequest = Body(..., title="Prediction Request"),
         prefer: Optional[str] = Header(default=None),
@@ -326,19 +493,85 @@ def create_app(
         # set on the prediction object
         request.id = prediction_id
 
+        # A retried request for a prediction that has already finished gets
+        # its result, rather than running it again.
+        completed = runner.completed_prediction(prediction_id)
+        if completed is not None:
+            return _completed_response(
+                request, completed, headers=completed.response_headers
+            )
+
+        if _shutting_down():
+            return _unavailable_response()
+
         # TODO: spec-compliant parsing of Prefer header.
         respond_async = prefer == "respond-async"
 
+        if not await _wait_for_slot(http_request, prediction_id=prediction_id):
+            return _unavailable_response()
+        # Shutdown may have started while the request was queued.
+        if _shutting_down():
+            return _unavailable_response()
+
+        # Clients that go away are expected to retry the same request to
+        # get the result, so the prediction isn't canceled when they do.
         with trace_context(make_trace_context(traceparent, tracestate)):
-            return _predict(
+            return await _predict(
                 request=request,
                 respond_async=respond_async,
             )
 
-    def _predict(
+    # Requests waiting for the runner to be free, oldest first. Only the
+    # oldest can take the slot, so they're served in the order they arrived.
+    slot_queue: Deque[object] = deque()
+
+    async def _wait_for_slot(
+        http_request: Request, prediction_id: Optional[str]
+    ) -> bool:
+        """
+        Returns whether the request can start its prediction. If another
+        prediction is running, or other requests are already waiting, the
+        request joins the back of the queue, as long as fewer than queue_depth
+        requests are already in it, and waits up to queue_timeout for its turn.
+        """
+        _check_setup_result()
+        current = runner.current_prediction()
+        if prediction_id and current is not None and current.id == prediction_id:
+            # A retry of the running prediction, which gets its result.
+            return True
+        # A reload has been requested, and the worker is about to be replaced.
+        if app.state.health == Health.STARTING:
+            return False
+        if not slot_queue and not runner.is_busy():
+            return True
+        if app.state.health != Health.READY or len(slot_queue) >= queue_depth:
+            return False
+
+        ticket = object()
+        slot_queue.append(ticket)
+        try:
+            deadline = time.monotonic() + queue_timeout
+            while time.monotonic() < deadline:
+                if app.state.health == Health.STARTING:
+                    return False
+                # The request leaves the queue and starts its prediction
+                # without yielding to the event loop in between, so nothing
+                # else can take the slot first.
+                if slot_queue[0] is ticket and not runner.is_busy():
+                    return True
+                # Stop waiting for clients that have gone away.
+                if await http_request.is_disconnected():
+                    return False
+                await asyncio.sleep(QUEUE_POLL_INTERVAL)
+            return False
+        finally:
+            slot_queue.remove(ticket)
+
+    async def _predict(
         *,
         request: Optional[PredictionRequest],
         respond_async: bool = False,
+        http_request: Optional[Request] = None,
     ) -> Response:
         # [compat] If no body is supplied, assume that this model can be run
         # with empty input. This will throw a ValidationError if that's not
@@ -359,43 +592,344 @@ def create_app(
                 upload=respond_async,
             )
         except RunnerBusyError:
-            return JSONResponse(
-                {"detail": "Already running a prediction"}, status_code=409
-            )
+            return _unavailable_response()
 
         if respond_async:
             return JSONResponse(jsonable_encoder(initial_response), status_code=202)
 
+        if (
+            http_request is not None
+            and output_is_iterator
+            and NDJSON_MEDIA_TYPE in http_request.headers.get("accept", "")
+        ):
+            return StreamingResponse(
+                _stream_ndjson(request, initial_response, async_result),
+                media_type=NDJSON_MEDIA_TYPE,
+            )
+
+        if http_request is not None:
+            await _cancel_if_disconnected(http_request, async_result)
+
         try:
             response = PredictionResponse(**async_result.get().dict())
         except ValidationError as e:
             _log_invalid_output(e)
             raise HTTPException(status_code=500, detail=str(e)) from e
 
+        return _completed_response(
+            request, response, headers=initial_response.response_headers
+        )
+
+    def _completed_response(
+        request: PredictionRequest, response: Any, headers: Dict[str, str]
+    ) -> Response:
+        """
+        Responds with a finished prediction. A retried request for one that
+        has already finished gets the same status and headers as the first.
+        """
+        response_object = _upload_outputs(request, response)
+
+        # A failed prediction is a successful request by default, but some
+        # clients and gateways expect failures to be reported as errors.
+        status_code = 200
+        if response.status == schema.Status.FAILED:
+            status_code = failed_prediction_http_status
+
+        # FIXME: clean up output files
+        encoded_response = jsonable_encoder(response_object)
+        return JSONResponse(
+            content=encoded_response,
+            status_code=status_code,
+            headers=headers,
+        )
+
+    async def _cancel_if_disconnected(
+        http_request: Request, async_result: PredictionTask
+    ) -> None:
+        """
+        Waits for a synchronous prediction to finish, canceling it if the
+        client disconnects, as nothing else will get its output.
+        """
+        while not async_result.ready():
+            if await http_request.is_disconnected():
+                log.info("client disconnected, canceling prediction")
+                runner.cancel(reason="client disconnected")
+                break
+            await asyncio.sleep(RESULT_POLL_INTERVAL)
+
+    async def _stream_ndjson(
+        request: PredictionRequest,
+        response: schema.PredictionResponse,
+        async_result: PredictionTask,
+    ) -> AsyncIterator[str]:
+        """
+        Streams each output of an iterator prediction as a line of JSON as
+        it's yielded, followed by a line with the rest of the response.
+        """
+        sent = 0
+        try:
+            while True:
+                # Check before reading the outputs, so none are missed if the
+                # prediction finishes in between.
+                done = async_result.ready()
+                outputs = response.output or []
+                for output in outputs[sent:]:
+                    encoded = jsonable_encoder(
+                        upload_files(
+                            output,
+                            upload_file=lambda fh: upload_file(
+                                fh, request.output_file_prefix  # type: ignore
+                            ),
+                        )
+                    )
+                    yield json.dumps({"output": encoded}) + "\n"
+                sent = len(outputs)
+                if done:
+                    break
+                await asyncio.sleep(RESULT_POLL_INTERVAL)
+        finally:
+            # The stream only stops early if the client went away, and nothing
+            # else will get the output.
+            if not async_result.ready():
+                log.info("client disconnected, canceling prediction")
+                runner.cancel(reason="client disconnected")
+
+        try:
+            final = async_result.get().dict(exclude={"output"})
+        except Exception as e:
+            # The response has already started, so the error can only be
+            # reported in the stream itself.
+            log.error("failed to get prediction result", exc_info=True)
+            final = {"status": schema.Status.FAILED, "error": str(e)}
+        yield json.dumps(jsonable_encoder(final)) + "\n"
+
+    def _upload_outputs(request: PredictionRequest, response: Any) -> Dict[str, Any]:
         response_object = response.dict()
         response_object["output"] = upload_files(
             response_object["output"],
             upload_file=lambda fh: upload_file(fh, request.output_file_prefix),  # type: ignore
         )
+        return response_object
 
-        # FIXME: clean up output files
-        encoded_response = jsonable_encoder(response_object)
-        return JSONResponse(content=encoded_response)
+    # This is left out of the schema, as it isn't derived from the model.
+    @limited
+    @app.post("/predictions/batch", include_in_schema=False)
+    async def predict_batch(
+        batch: schema.BatchPredictionRequest = Body(...),
+        traceparent: Optional[str] = Header(default=None, include_in_schema=False),
+        tracestate: Optional[str] = Header(default=None, include_in_schema=False),
+    ) -> Any:
+        """
+        Run a prediction for each of a list of inputs, in order
+        """
+        _check_setup_result()
+        if (
+            _shutting_down()
+            or app.state.health == Health.STARTING
+            or runner.is_busy()
+        ):
+            return _unavailable_response()
+
+        results = []
+        with trace_context(make_trace_context(traceparent, tracestate)):
+            for i, input in enumerate(batch.inputs):
+                try:
+                    request = PredictionRequest(input=input or {})
+                except ValidationError as e:
+                    results.append({"status": schema.Status.FAILED, "error": str(e)})
+                    continue
+
+                # The runner has a single slot, so each prediction has to
+                # finish before the next can start. If something else takes
+                # the slot in between, the rest of the inputs fail, so there's
+                # still a result for every input.
+                try:
+                    _, async_result = runner.predict(request, upload=False)
+                except RunnerBusyError:
+                    busy = {"status": schema.Status.FAILED, "error": "busy"}
+                    results.extend(busy for _ in batch.inputs[i:])
+                    break
+
+                while not async_result.ready():
+                    await asyncio.sleep(RESULT_POLL_INTERVAL)
+
+                try:
+                    response = PredictionResponse(**async_result.get().dict())
+                except ValidationError as e:
+                    _log_invalid_output(e)
+                    results.append({"status": schema.Status.FAILED, "error": str(e)})
+                    continue
+
+                results.append(_upload_outputs(request, response))
+
+        return JSONResponse(jsonable_encoder({"results": results}))
+
+    @app.get(
+        "/predictions/{prediction_id}",
+        response_model=PredictionResponse,
+        response_model_exclude_unset=True,
+    )
+    async def get_prediction(
+        prediction_id: str = Path(..., title="Prediction ID"),
+    ) -> Any:
+        """
+        Get the current state of a prediction
+        """
+        try:
+            response = runner.get_prediction(prediction_id)
+        except UnknownPredictionError:
+            return JSONResponse({"detail": "Prediction not found"}, status_code=404)
+
+        headers = {}
+        if not schema.Status.is_terminal(response.status):
+            headers["Retry-After"] = str(retry_after)
+        return JSONResponse(jsonable_encoder(response), headers=headers)
 
     @app.post("/predictions/{prediction_id}/cancel")
-    async def cancel(prediction_id: str = Path(..., title="Prediction ID")) -> Any:
+    async def cancel(
+        prediction_id: str = Path(..., title="Prediction ID"),
+        request: Optional[schema.CancelRequest] = Body(default=None),
+    ) -> Any:
         """
         Cancel a running prediction
         """
         if not runner.is_busy():
             return JSONResponse({}, status_code=404)
+        reason = request.reason if request is not None else None
         try:
-            runner.cancel(prediction_id)
+            runner.cancel(prediction_id, reason=reason)
         except UnknownPredictionError:
             return JSONResponse({}, status_code=404)
         else:
             return JSONResponse({}, status_code=200)
 
+    # Admin routes are only served when a token is configured, and are left
+    # out of the schema because they're for operators, not clients.
+    if admin_token:
+
+        @app.post("/admin/slots/{slot_id}/cancel", include_in_schema=False)
+        async def admin_cancel_slot(
+            slot_id: int = Path(..., title="Slot ID"),
+            authorization: Optional[str] = Header(default=None),
+        ) -> Any:
+            """
+            Cancel whatever prediction is running on a slot
+            """
+            if not hmac.compare_digest(authorization or "", f"Bearer {admin_token}"):
+                return JSONResponse({"detail": "Unauthorized"}, status_code=401)
+
+            # The runner only has a single slot.
+            prediction = (
+                runner.current_prediction() if slot_id == SLOT_INDEX else None
+            )
+            if prediction is None:
+                return JSONResponse({"detail": "Slot is idle"}, status_code=404)
+
+            runner.cancel(reason="admin")
+            return JSONResponse({"id": prediction.id}, status_code=200)
+
+        @app.post("/admin/cancel-all", include_in_schema=False)
+        async def admin_cancel_all(
+            authorization: Optional[str] = Header(default=None),
+        ) -> Any:
+            """
+            Cancel every running prediction, e.g. before draining the server
+            """
+            if not hmac.compare_digest(authorization or "", f"Bearer {admin_token}"):
+                return JSONResponse({"detail": "Unauthorized"}, status_code=401)
+
+            # The runner only has a single slot, so there's at most one.
+            canceled = 0
+            prediction = runner.current_prediction()
+            if prediction is not None and not schema.Status.is_terminal(
+                prediction.status
+            ):
+                try:
+                    runner.cancel(prediction_id=prediction.id, reason="admin")
+                    canceled += 1
+                except UnknownPredictionError:
+                    # It finished before we got to it.
+                    pass
+            return JSONResponse({"canceled": canceled}, status_code=200)
+
+        @app.get("/admin/predictions", include_in_schema=False)
+        async def admin_list_predictions(
+            authorization: Optional[str] = Header(default=None),
+        ) -> Any:
+            """
+            List the most recently completed predictions, newest first
+            """
+            if not hmac.compare_digest(authorization or "", f"Bearer {admin_token}"):
+                return JSONResponse({"detail": "Unauthorized"}, status_code=401)
+
+            return JSONResponse(
+                jsonable_encoder({"predictions": runner.recent_predictions()}),
+                status_code=200,
+            )
+
+        @app.post("/admin/reload", include_in_schema=False)
+        async def admin_reload(
+            authorization: Optional[str] = Header(default=None),
+        ) -> Any:
+            """
+            Restart the worker to pick up changes to the predictor's code
+            """
+            if not hmac.compare_digest(authorization or "", f"Bearer {admin_token}"):
+                return JSONResponse({"detail": "Unauthorized"}, status_code=401)
+
+            _check_setup_result()
+            if app.state.health == Health.STARTING:
+                return JSONResponse(
+                    {"detail": "Setup has not finished yet"}, status_code=409
+                )
+
+            # New predictions are refused while the running one, if any,
+            # finishes, and then until the new worker has been set up.
+            app.state.health = Health.STARTING
+
+            def reload_when_idle() -> None:
+                while True:
+                    if not runner.wait_for_idle(timeout=1):
+                        continue
+                    try:
+                        app.state.setup_task = runner.reload()
+                        return
+                    except RunnerBusyError:
+                        # A prediction took the slot as it came free, so wait
+                        # for that one to finish too.
+                        time.sleep(QUEUE_POLL_INTERVAL)
+
+            threading.Thread(target=reload_when_idle, daemon=True).start()
+            return JSONResponse({"status": Health.STARTING.name}, status_code=202)
+
+    def _shutting_down() -> bool:
+        # Once shutdown has started, running predictions are left to finish
+        # but new ones are refused.
+        return shutdown_event is not None and shutdown_event.is_set()
+
+    def _unavailable_response() -> Response:
+        # Tell clients when it's worth trying again, so they can back off
+        # rather than retrying in a tight loop.
+        headers = {"Retry-After": str(retry_after)}
+        if _shutting_down():
+            return JSONResponse(
+                {"detail": "Server is shutting down"},
+                status_code=503,
+                headers=headers,
+            )
+        _check_setup_result()
+        if app.state.health == Health.STARTING:
+            return JSONResponse(
+                {"detail": "Setup has not finished yet"},
+                status_code=503,
+                headers=headers,
+            )
+        return JSONResponse(
+            {"detail": "Already running a prediction"},
+            status_code=409,
+            headers=headers,
+        )
+
     def _check_setup_result() -> Any:
         if app.state.setup_task is None:
             return
@@ -418,6 +952,35 @@ def create_app(
     return app
 
 
+def create_multi_app(apps: Dict[str, MyFastAPI]) -> FastAPI:
+    """
+    Serves several models from one server. Each model's app, as made by
+    create_app(), is mounted with all of its routes under /models/<name>, e.g.
+    /models/<name>/predictions and /models/<name>/health-check.
+    """
+    app = FastAPI(title="Cog")
+    for name, model_app in apps.items():
+        app.mount(f"/models/{name}", model_app)
+
+    # Mounted apps don't get startup and shutdown events of their own, and
+    # those are what set up and shut down each model's runner.
+    @app.on_event("startup")
+    async def startup() -> None:
+        for model_app in apps.values():
+            await model_app.router.startup()
+
+    @app.on_event("shutdown")
+    async def shutdown() -> None:
+        for model_app in apps.values():
+            await model_app.router.shutdown()
+
+    @app.get("/")
+    async def root() -> Any:
+        return {"models": [f"/models/{name}" for name in apps]}
+
+    return app
+
+
 def _log_invalid_output(error: Any) -> None:
     log.error(
         textwrap.dedent(
@@ -481,7 +1044,7 @@ def _cpu_count() -> int:
         return os.cpu_count() or 1
 
 
-if __name__ == "__main__":
+def make_parser() -> argparse.ArgumentParser:
     parser = argparse.ArgumentParser(description="Cog HTTP server")
     parser.add_argument(
         "--host",
@@ -519,7 +1082,179 @@ if __name__ == "__main__":
         choices=["predict", "train"],
         help="Experimental: Run in 'predict' or 'train' mode",
     )
+    parser.add_argument(
+        "--isolate-cwd",
+        dest="isolate_cwd",
+        action="store_true",
+        help="Run each prediction in its own temporary working directory",
+    )
+    parser.add_argument(
+        "--retry-after",
+        dest="retry_after",
+        type=int,
+        default=1,
+        help="Seconds clients are told to wait before retrying when the server is busy or starting",
+    )
+    parser.add_argument(
+        "--failed-prediction-http-status",
+        dest="failed_prediction_http_status",
+        type=int,
+        default=200,
+        choices=[200, 500],
+        help="HTTP status code to respond with when a synchronous prediction fails",
+    )
+    parser.add_argument(
+        "--max-inline-output-size",
+        dest="max_inline_output_size",
+        type=int,
+        default=DEFAULT_MAX_INLINE_OUTPUT_SIZE,
+        help="Outputs larger than this many bytes are passed from the model process via a temporary file",
+    )
+    parser.add_argument(
+        "--max-worker-restarts",
+        dest="max_worker_restarts",
+        type=int,
+        default=0,
+        help="Restart the worker if it crashes during a prediction, at most this many times per --worker-restart-window",
+    )
+    parser.add_argument(
+        "--worker-restart-window",
+        dest="worker_restart_window",
+        type=float,
+        default=300.0,
+        help="Window in seconds over which --max-worker-restarts is counted",
+    )
+    parser.add_argument(
+        "--gpu-wait-timeout",
+        dest="gpu_wait_timeout",
+        type=float,
+        default=None,
+        help="Wait up to this many seconds for a GPU to become available before running setup",
+    )
+    parser.add_argument(
+        "--max-body-size",
+        dest="max_body_size",
+        type=int,
+        default=DEFAULT_MAX_BODY_SIZE,
+        help="Reject prediction and training requests with bodies larger than this many bytes",
+    )
+    parser.add_argument(
+        "--queue-depth",
+        dest="queue_depth",
+        type=int,
+        default=0,
+        help="Number of prediction requests that can wait for a busy model to become free",
+    )
+    parser.add_argument(
+        "--queue-timeout",
+        dest="queue_timeout",
+        type=float,
+        default=30.0,
+        help="Seconds a queued prediction request waits before giving up",
+    )
+    parser.add_argument(
+        "--completed-prediction-ttl",
+        dest="completed_prediction_ttl",
+        type=float,
+        default=300.0,
+        help="Seconds to keep the results of completed predictions for",
+    )
+    parser.add_argument(
+        "--max-completed-predictions",
+        dest="max_completed_predictions",
+        type=int,
+        default=100,
+        help="Maximum number of completed prediction results to keep",
+    )
+    parser.add_argument(
+        "--slot-release-timeout",
+        dest="slot_release_timeout",
+        type=float,
+        default=300.0,
+        help="Shut down if a completed prediction still hasn't finished after this many seconds",
+    )
+    parser.add_argument(
+        "--gzip-min-size",
+        dest="gzip_min_size",
+        type=int,
+        default=None,
+        help="Compress responses of at least this many bytes for clients that accept gzip",
+    )
+    parser.add_argument(
+        "--recent-predictions",
+        dest="recent_predictions",
+        type=int,
+        default=20,
+        help="Number of recent predictions to list at GET /admin/predictions",
+    )
+    parser.add_argument(
+        "--keep-alive-timeout",
+        dest="keep_alive_timeout",
+        type=int,
+        default=5,
+        help="Seconds to keep idle connections open for, waiting for another request",
+    )
+    parser.add_argument(
+        "--shutdown-grace-period",
+        dest="shutdown_grace_period",
+        type=float,
+        default=30.0,
+        help="Seconds to wait for a running prediction to finish when shutting down",
+    )
+    parser.add_argument(
+        "--model",
+        dest="models",
+        action="append",
+        default=[],
+        help="Serve a predictor under /models/<name>, given as <name>=<path>:<class>, instead of the one in cog.yaml. Can be repeated",
+    )
+    parser.add_argument(
+        "--no-metrics",
+        dest="enable_metrics",
+        action="store_false",
+        help="Don't serve Prometheus metrics at /metrics",
+    )
+    return parser
+
+
+def make_server_config(
+    app: FastAPI, args: argparse.Namespace, port: int
+) -> uvicorn.Config:
+    return uvicorn.Config(
+        app,
+        host=args.host,
+        port=port,
+        log_config=None,
+        # This is the default, but to be explicit: only run a single worker
+        workers=1,
+        timeout_keep_alive=args.keep_alive_timeout,
+    )
+
+
+def parse_models(
+    parser: argparse.ArgumentParser, models: List[str]
+) -> Dict[str, str]:
+    """
+    Parses the --model options into the predictor to serve under each name,
+    exiting with a usage error if one is malformed or a name is repeated.
+    """
+    predictor_refs: Dict[str, str] = {}
+    for model in models:
+        name, _, predictor_ref = model.partition("=")
+        if not name or "/" in name or not predictor_ref:
+            parser.error(
+                f"argument --model: expected <name>=<path>:<class>, got {model!r}"
+            )
+        if name in predictor_refs:
+            parser.error(f"argument --model: {name!r} is given more than once")
+        predictor_refs[name] = predictor_ref
+    return predictor_refs
+
+
+if __name__ == "__main__":
+    parser = make_parser()
     args = parser.parse_args()
+    models = parse_models(parser, args.models)
 
     # log level is configurable so we can make it quiet or verbose for `cog predict`
     # cog predict --debug       # -> debug
@@ -538,29 +1273,50 @@ if __name__ == "__main__":
             threads = _cpu_count()
 
     shutdown_event = threading.Event()
-    app = create_app(
-        config=config,
+    app_options: Dict[str, Any] = dict(
         shutdown_event=shutdown_event,
         threads=threads,
         upload_url=args.upload_url,
         mode=args.mode,
+        isolate_cwd=args.isolate_cwd,
+        retry_after=args.retry_after,
+        failed_prediction_http_status=args.failed_prediction_http_status,
+        admin_token=os.environ.get("COG_ADMIN_TOKEN"),
+        max_inline_output_size=args.max_inline_output_size,
+        max_worker_restarts=args.max_worker_restarts,
+        worker_restart_window=args.worker_restart_window,
+        enable_metrics=args.enable_metrics,
+        gpu_wait_timeout=args.gpu_wait_timeout,
+        max_body_size=args.max_body_size,
+        queue_depth=args.queue_depth,
+        queue_timeout=args.queue_timeout,
+        completed_prediction_ttl=args.completed_prediction_ttl,
+        max_completed_predictions=args.max_completed_predictions,
+        slot_release_timeout=args.slot_release_timeout,
+        gzip_min_size=args.gzip_min_size,
+        recent_predictions=args.recent_predictions,
     )
 
-    host: str = args.host
+    app: FastAPI
+    model_apps: List[MyFastAPI]
+    if models:
+        apps: Dict[str, MyFastAPI] = {}
+        for name, predictor_ref in models.items():
+            model_config = {**config, "predict": predictor_ref}
+            model_config.pop("train", None)
+            apps[name] = create_app(config=model_config, **app_options)
+        app = create_multi_app(apps)
+        model_apps = list(apps.values())
+    else:
+        app = create_app(config=config, **app_options)
+        model_apps = [app]
 
     port = int(os.getenv("PORT", 5000))
     if is_port_in_use(port):
         log.error(f"Port {port} is already in use")
         sys.exit(1)
 
-    server_config = uvicorn.Config(
-        app,
-        host=host,
-        port=port,
-        log_config=None,
-        # This is the default, but to be explicit: only run a single worker
-        workers=1,
-    )
+    server_config = make_server_config(app, args, port)
 
     if args.await_explicit_shutdown:
         signal.signal(signal.SIGTERM, signal_ignore)
@@ -574,10 +1330,40 @@ if __name__ == "__main__":
         shutdown_event.wait()
     except KeyboardInterrupt:
         pass
+    else:
+        # New predictions are refused from here on, but give any that are
+        # already running a chance to finish. Each model's runner is waited
+        # for at the same time, so the grace period isn't multiplied by the
+        # number of models.
+        def wait_for_idle(runner: PredictionRunner) -> None:
+            if not runner.wait_for_idle(timeout=args.shutdown_grace_period):
+                log.warn(
+                    "prediction still running after shutdown grace period",
+                    grace_period=args.shutdown_grace_period,
+                )
+
+        waiters = [
+            threading.Thread(target=wait_for_idle, args=(model_app.state.runner,))
+            for model_app in model_apps
+            if model_app.state.runner is not None
+        ]
+        for waiter in waiters:
+            waiter.start()
+        for waiter in waiters:
+            waiter.join()
+        # Terminal webhooks that failed are retried in the background, so
+        # give those a chance to be delivered too.
+        if not wait_for_pending_webhooks(timeout=args.shutdown_grace_period):
+            log.warn(
+                "webhooks still being retried after shutdown grace period",
+                grace_period=args.shutdown_grace_period,
+            )
 
     s.stop()
 
     # return error exit code when setup failed and cog is running in interactive mode (not k8s)
-    if app.state.setup_result and not args.await_explicit_shutdown:
-        if app.state.setup_result.status == schema.Status.FAILED:
-            exit(-1)
+    if not args.await_explicit_shutdown:
+        for model_app in model_apps:
+            setup_result = model_app.state.setup_result
+            if setup_result and setup_result.status == schema.Status.FAILED:
+                exit(-1)
@@ -0,0 +1,110 @@
+import threading
+from typing import Dict, List, Optional, Tuple
+
+from ..schema import Status
+
+# Predictions take anywhere from milliseconds to many minutes, so the buckets
+# are spread wider than the Prometheus client defaults.
+PREDICT_TIME_BUCKETS = (0.1, 0.25, 0.5, 1, 2.5, 5, 10, 30, 60, 120, 300, 600)
+
+CONTENT_TYPE = "text/plain; version=0.0.4; charset=utf-8"
+
+
+class Metrics:
+    """
+    Counts predictions and how long they take, and renders them in the
+    Prometheus text exposition format.
+    """
+
+    def __init__(self) -> None:
+        self._lock = threading.Lock()
+        self._started = 0
+        self._completed: Dict[Status, int] = {
+            Status.SUCCEEDED: 0,
+            Status.FAILED: 0,
+            Status.CANCELED: 0,
+        }
+        self._bucket_counts = [0] * len(PREDICT_TIME_BUCKETS)
+        self._predict_time_count = 0
+        self._predict_time_sum = 0.0
+        self._setup_duration: Optional[float] = None
+
+    def prediction_started(self) -> None:
+        with self._lock:
+            self._started += 1
+
+    def prediction_completed(self, status: Status, predict_time: float) -> None:
+        with self._lock:
+            self._completed[status] += 1
+            for i, bound in enumerate(PREDICT_TIME_BUCKETS):
+                if predict_time <= bound:
+                    self._bucket_counts[i] += 1
+            self._predict_time_count += 1
+            self._predict_time_sum += predict_time
+
+    def setup_completed(self, duration: float) -> None:
+        with self._lock:
+            self._setup_duration = duration
+
+    def render(self, busy_slots: int) -> str:
+        with self._lock:
+            lines: List[str] = []
+
+            def metric(
+                name: str,
+                kind: str,
+                description: str,
+                samples: List[Tuple[str, float]],
+            ) -> None:
+                lines.append(f"# HELP {name} {description}")
+                lines.append(f"# TYPE {name} {kind}")
+                for suffix, value in samples:
+                    lines.append(f"{name}{suffix} {value}")
+
+            metric(
+                "cog_predictions_started_total",
+                "counter",
+                "Number of predictions started.",
+                [("", self._started)],
+            )
+            metric(
+                "cog_predictions_completed_total",
+                "counter",
+                "Number of predictions completed, by status.",
+                [
+                    (f'{{status="{status.value}"}}', count)
+                    for status, count in self._completed.items()
+                ],
+            )
+
+            buckets: List[Tuple[str, float]] = [
+                (f'_bucket{{le="{bound}"}}', count)
+                for bound, count in zip(PREDICT_TIME_BUCKETS, self._bucket_counts)
+            ]
+            buckets.append(('_bucket{le="+Inf"}', self._predict_time_count))
+            metric(
+                "cog_predict_time_seconds",
+                "histogram",
+                "Time taken to run predictions.",
+                buckets
+                + [
+                    ("_sum", self._predict_time_sum),
+                    ("_count", self._predict_time_count),
+                ],
+            )
+
+            metric(
+                "cog_busy_slots",
+                "gauge",
+                "Number of predictions currently running.",
+                [("", busy_slots)],
+            )
+            if self._setup_duration is not None:
+                metric(
+                    "cog_setup_duration_seconds",
+                    "gauge",
+                    "Time taken by the most recent setup.",
+                    [("", self._setup_duration)],
+                )
+
+            return "\n".join(lines) + "\n"
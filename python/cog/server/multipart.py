@@ -0,0 +1,131 @@
+import base64
+import json
+import mimetypes
+from email.message import EmailMessage
+from email.parser import BytesParser
+from email.policy import HTTP
+from typing import Any, Dict, List, Tuple
+
+from fastapi.responses import JSONResponse
+from starlette.types import ASGIApp, Message, Receive, Scope, Send
+
+
+class MultipartRequestError(Exception):
+    pass
+
+
+class MultipartPredictionMiddleware:
+    """
+    Converts multipart/form-data requests to the given path prefixes into the
+    JSON requests the prediction routes expect, so clients can upload files
+    without base64-encoding them.
+
+    A part named "input" holds a JSON object with the inputs that aren't
+    files, and each file part is the input it's named after. File parts named
+    like "paths[]" are passed as a list, even if there's only one.
+    """
+
+    def __init__(self, app: ASGIApp, paths: Tuple[str, ...]) -> None:
+        self.app = app
+        self.paths = paths
+
+    async def __call__(self, scope: Scope, receive: Receive, send: Send) -> None:
+        if scope["type"] != "http" or not scope["path"].startswith(self.paths):
+            await self.app(scope, receive, send)
+            return
+
+        headers: Dict[bytes, bytes] = dict(scope["headers"])
+        content_type = headers.get(b"content-type", b"").decode("latin-1")
+        if not content_type.startswith("multipart/form-data"):
+            await self.app(scope, receive, send)
+            return
+
+        body = b""
+        more_body = True
+        while more_body:
+            message = await receive()
+            if message["type"] != "http.request":
+                return
+            body += message.get("body", b"")
+            more_body = message.get("more_body", False)
+
+        try:
+            request = parse_multipart_request(content_type, body)
+        except MultipartRequestError as e:
+            response = JSONResponse({"detail": str(e)}, status_code=422)
+            await response(scope, receive, send)
+            return
+
+        encoded = json.dumps(request).encode("utf-8")
+        headers[b"content-type"] = b"application/json"
+        headers[b"content-length"] = str(len(encoded)).encode("latin-1")
+        scope = dict(scope, headers=list(headers.items()))
+
+        replayed = False
+
+        async def replay() -> Message:
+            nonlocal replayed
+            if replayed:
+                return await receive()
+            replayed = True
+            return {"type": "http.request", "body": encoded, "more_body": False}
+
+        await self.app(scope, replay, send)
+
+
+def parse_multipart_request(content_type: str, body: bytes) -> Dict[str, Any]:
+    """
+    Returns the JSON prediction request for a multipart/form-data body, with
+    files passed as data URLs.
+    """
+    message = BytesParser(policy=HTTP).parsebytes(
+        f"Content-Type: {content_type}\r\n\r\n".encode("latin-1") + body
+    )
+    if not message.is_multipart():
+        raise MultipartRequestError("Request body is not valid multipart/form-data")
+
+    input: Dict[str, Any] = {}
+    files: Dict[str, List[str]] = {}
+    for part in message.iter_parts():
+        assert isinstance(part, EmailMessage)
+        name = part.get_param("name", header="content-disposition")
+        if not isinstance(name, str):
+            raise MultipartRequestError("Every part must have a name")
+        payload = part.get_payload(decode=True)
+        assert isinstance(payload, bytes)
+
+        filename = part.get_filename()
+        if filename is None:
+            if name != "input":
+                raise MultipartRequestError(
+                    f"Unexpected part '{name}': only 'input' and files are allowed"
+                )
+            try:
+                input = json.loads(payload)
+            except ValueError as e:
+                raise MultipartRequestError(f"'input' is not valid JSON: {e}") from e
+            if not isinstance(input, dict):
+                raise MultipartRequestError("'input' must be a JSON object")
+            continue
+
+        mime_type = part.get_content_type()
+        if mime_type == "text/plain" and "content-type" not in part:
+            # No content type was sent, so go by the filename.
+            guessed, _ = mimetypes.guess_type(filename)
+            mime_type = guessed or "application/octet-stream"
+        encoded = base64.b64encode(payload).decode("ascii")
+        files.setdefault(name, []).append(f"data:{mime_type};base64,{encoded}")
+
+    for name, urls in files.items():
+        is_list = name.endswith("[]")
+        name = name[:-2] if is_list else name
+        if name in input:
+            raise MultipartRequestError(f"Input '{name}' is set by more than one part")
+        if not is_list and len(urls) > 1:
+            raise MultipartRequestError(
+                f"Input '{name}' has more than one file; name it '{name}[]' "
+                "to pass a list"
+            )
+        input[name] = urls if is_list else urls[0]
+
+    return {"input": input}
@@ -1,7 +1,8 @@
+import glob
 import logging
 import os
 from pathlib import Path
-from typing import Union
+from typing import Optional, Union
 
 log = logging.getLogger(__name__)
 
@@ -10,15 +11,24 @@ PathLike = Union[Path, str, None]
 
 class ProbeHelper:
     _root = Path("/var/run/cog")
+    _ready_file = _root / "ready"
     _enabled = False
 
-    def __init__(self, root: PathLike = None) -> None:
-        if "KUBERNETES_SERVICE_HOST" not in os.environ:
+    def __init__(self, root: PathLike = None, ready_file: PathLike = None) -> None:
+        # A readiness file set explicitly is always written, wherever we're
+        # running. Otherwise, we only write one under Kubernetes.
+        if ready_file is None:
+            ready_file = os.environ.get("COG_READY_FILE") or None
+
+        if ready_file is not None:
+            self._ready_file = Path(ready_file)
+            self._root = self._ready_file.parent
+        elif "KUBERNETES_SERVICE_HOST" not in os.environ:
             log.info("Not running in Kubernetes: disabling probe helpers.")
             return
-
-        if root is not None:
+        elif root is not None:
             self._root = Path(root)
+            self._ready_file = self._root / "ready"
 
         try:
             self._root.mkdir(exist_ok=True, parents=True)
@@ -33,4 +43,24 @@ class ProbeHelper:
 
     def ready(self) -> None:
         if self._enabled:
-            (self._root / "ready").touch()
+            self._ready_file.touch()
+
+
+def gpu_available() -> bool:
+    """
+    Returns whether an NVIDIA GPU device is visible to this container.
+    """
+    return bool(glob.glob("/dev/nvidia[0-9]*"))
+
+
+def rss_bytes(pid: Union[int, str]) -> Optional[int]:
+    """
+    Returns the resident set size of a process in bytes, or None if it can't be
+    read, e.g. because we're not on Linux or the process has exited.
+    """
+    try:
+        with open(f"/proc/{pid}/statm") as f:
+            resident_pages = int(f.read().split()[1])
+    except (OSError, ValueError, IndexError):
+        return None
+    return resident_pages * os.sysconf("SC_PAGE_SIZE")
@@ -1,5 +1,5 @@
 import time
-from typing import Any, Dict
+from typing import Any, Dict, Optional
 
 from ..schema import Status
 
@@ -7,16 +7,32 @@ from ..schema import Status
 class ResponseThrottler:
     def __init__(self, response_interval: float) -> None:
         self.last_sent_response_time = 0.0
+        self.last_sent_status: Optional[str] = None
         self.response_interval = response_interval
 
     def should_send_response(self, response: Dict[str, Any]) -> bool:
         if Status.is_terminal(response["status"]):
             return True
 
+        # Once a prediction has finished, later updates would arrive after
+        # the terminal one, so they're dropped.
+        if self.last_sent_status is not None and Status.is_terminal(
+            self.last_sent_status
+        ):
+            return False
+
+        # Changes of status, e.g. from starting to processing, always go out.
+        if (
+            self.last_sent_status is not None
+            and response["status"] != self.last_sent_status
+        ):
+            return True
+
         return self.seconds_since_last_response() >= self.response_interval
 
-    def update_last_sent_response_time(self) -> None:
+    def update_last_sent_response_time(self, status: Optional[str] = None) -> None:
         self.last_sent_response_time = time.time()
+        self.last_sent_status = status
 
     def seconds_since_last_response(self) -> float:
         return time.time() - self.last_sent_response_time
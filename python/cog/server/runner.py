@@ -1,11 +1,14 @@
 import io
+import os
 import sys
 import threading
+import time
 import traceback
 import typing  # TypeAlias, py3.10
+from collections import deque
 from datetime import datetime, timezone
 from multiprocessing.pool import AsyncResult, ThreadPool
-from typing import Any, Callable, Optional, Tuple, Union, cast
+from typing import Any, Callable, Deque, Dict, List, Optional, Tuple, Union, cast
 
 import requests
 import structlog
@@ -17,15 +20,45 @@ from requests.packages.urllib3.util.retry import Retry  # type: ignore
 from .. import schema, types
 from ..files import put_file_to_signed_endpoint
 from ..json import upload_files
-from .eventtypes import Done, Heartbeat, Log, PredictionOutput, PredictionOutputType
-from .probes import ProbeHelper
-from .telemetry import current_trace_context
+from ..predictor import get_deprecation_warnings
+from .eventtypes import (
+    Done,
+    Heartbeat,
+    Log,
+    PredictionMetric,
+    PredictionOutput,
+    PredictionOutputType,
+    PredictionResponseHeader,
+)
+from .exceptions import FatalWorkerException
+from .metrics import Metrics
+from .probes import ProbeHelper, gpu_available, rss_bytes
+from .scope import validate_response_header
+from .telemetry import current_trace_context, get_trace_id, span
 from .useragent import get_user_agent
 from .webhook import SKIP_START_EVENT, webhook_caller_filtered
-from .worker import Worker
+from .worker import DEFAULT_MAX_INLINE_OUTPUT_SIZE, Worker
 
 log = structlog.get_logger("cog.server.runner")
 
+# How often to check whether a GPU has become available while waiting for one.
+GPU_POLL_INTERVAL = 1.0
+
+# The runner only has a single slot, so every prediction runs on slot 0. It's
+# included in logs so they can be correlated with the admin slot routes.
+SLOT_INDEX = 0
+
+# A prediction's logs are kept in memory and sent in every response, so they're
+# capped, in characters, to stop a chatty predictor from bloating them. Long
+# lines are truncated too, but aren't by default.
+DEFAULT_MAX_PREDICTION_LOGS_SIZE = 10 * 1024 * 1024
+LOGS_TRUNCATED_MARKER = "\n...log truncated...\n"
+
+_max_logs_size = int(
+    os.environ.get("COG_MAX_PREDICTION_LOGS_SIZE", DEFAULT_MAX_PREDICTION_LOGS_SIZE)
+)
+_max_log_line_length = os.environ.get("COG_MAX_LOG_LINE_LENGTH")
+
 
 class FileUploadError(Exception):
     pass
@@ -47,6 +80,14 @@ class SetupResult:
     status: schema.Status
 
 
+@define
+class SlotStats:
+    total: int
+    idle: int
+    busy: int
+    defunct: int
+
+
 PredictionTask: "typing.TypeAlias" = "AsyncResult[schema.PredictionResponse]"
 SetupTask: "typing.TypeAlias" = "AsyncResult[SetupResult]"
 if sys.version_info < (3, 9):
@@ -62,23 +103,79 @@ class PredictionRunner:
         predictor_ref: str,
         shutdown_event: Optional[threading.Event],
         upload_url: Optional[str] = None,
+        isolate_cwd: bool = False,
+        max_inline_output_size: int = DEFAULT_MAX_INLINE_OUTPUT_SIZE,
+        max_worker_restarts: int = 0,
+        worker_restart_window: float = 300.0,
+        on_worker_restart: Optional[Callable[[SetupTask], None]] = None,
+        gpu_wait_timeout: Optional[float] = None,
+        completed_prediction_ttl: float = 300.0,
+        max_completed_predictions: int = 100,
+        slot_release_timeout: float = 300.0,
+        recent_predictions: int = 20,
     ) -> None:
         self._thread = None
         self._threadpool = ThreadPool(processes=1)
+        self._predictor_ref = predictor_ref
+        self._isolate_cwd = isolate_cwd
+        self._max_inline_output_size = max_inline_output_size
+        self._gpu_wait_timeout = gpu_wait_timeout
 
         self._response: Optional[schema.PredictionResponse] = None
+        self._event_handler: Optional[PredictionEventHandler] = None
         self._result: Optional[RunnerTask] = None
 
-        self._worker = Worker(predictor_ref=predictor_ref)
+        # Logs from the most recent setup, appended to as they are written.
+        self._setup_logs: List[str] = []
+
+        # Recently completed predictions by ID, oldest first, so that clients
+        # can fetch their results after the runner has moved on. They're kept
+        # for completed_prediction_ttl seconds, and at most
+        # max_completed_predictions of them are kept at once.
+        self._completed: Dict[str, Tuple[float, schema.PredictionResponse]] = {}
+        self._completed_prediction_ttl = completed_prediction_ttl
+        self._max_completed_predictions = max_completed_predictions
+
+        # A short summary of the last recent_predictions predictions, newest
+        # last, for operators debugging the server. Unlike the results above,
+        # these aren't expired, but there are few of them and they're small.
+        self._recent: Deque[Dict[str, Any]] = deque(maxlen=recent_predictions)
+
+        # If a prediction has completed but its task still hasn't finished
+        # after slot_release_timeout seconds, e.g. because sending the final
+        # webhook is stuck, the slot would otherwise stay busy forever.
+        self._slot_release_timeout = slot_release_timeout
+
+        self._worker = self._make_worker()
         self._should_cancel = threading.Event()
 
         self._shutdown_event = shutdown_event
         self._upload_url = upload_url
 
+        # If the worker crashes during a prediction, it's restarted at most
+        # max_worker_restarts times in any worker_restart_window seconds.
+        self._max_worker_restarts = max_worker_restarts
+        self._worker_restart_window = worker_restart_window
+        self._worker_restarts: Deque[float] = deque()
+        self._on_worker_restart = on_worker_restart
+
+        self.metrics = Metrics()
+
+    def _make_worker(self) -> Worker:
+        return Worker(
+            predictor_ref=self._predictor_ref,
+            isolate_cwd=self._isolate_cwd,
+            max_inline_output_size=self._max_inline_output_size,
+        )
+
     def setup(self) -> SetupTask:
         if self.is_busy():
             raise RunnerBusyError()
 
+        self._result = self._start_setup()
+        return self._result
+
+    def _start_setup(self) -> SetupTask:
         def handle_error(error: BaseException) -> None:
             # Re-raise the exception in order to more easily capture exc_info,
             # and then trigger shutdown, as we have no easy way to resume
@@ -90,12 +187,21 @@ class PredictionRunner:
                 if self._shutdown_event is not None:
                     self._shutdown_event.set()
 
-        self._result = self._threadpool.apply_async(
+        def record_duration(result: SetupResult) -> None:
+            duration = (result.completed_at - result.started_at).total_seconds()
+            self.metrics.setup_completed(duration)
+
+        self._setup_logs = []
+        return self._threadpool.apply_async(
             func=setup,
-            kwds={"worker": self._worker},
+            kwds={
+                "worker": self._worker,
+                "gpu_wait_timeout": self._gpu_wait_timeout,
+                "logs": self._setup_logs,
+            },
+            callback=record_duration,
             error_callback=handle_error,
         )
-        return self._result
 
     # TODO: Make the return type AsyncResult[schema.PredictionResponse] when we
     # no longer have to support Python 3.8
@@ -118,14 +224,20 @@ class PredictionRunner:
 
         # Set up logger context for main thread. The same thing happens inside
         # the predict thread.
+        trace_id = get_trace_id(current_trace_context())
         structlog.contextvars.clear_contextvars()
-        structlog.contextvars.bind_contextvars(prediction_id=prediction.id)
+        structlog.contextvars.bind_contextvars(
+            prediction_id=prediction.id, slot_index=SLOT_INDEX
+        )
+        if trace_id is not None:
+            structlog.contextvars.bind_contextvars(trace_id=trace_id)
 
         self._should_cancel.clear()
         upload_url = self._upload_url if upload else None
         event_handler = create_event_handler(
             prediction,
             upload_url=upload_url,
+            metrics=self.metrics,
         )
 
         def cleanup(_: Optional[schema.PredictionResponse] = None) -> None:
@@ -141,10 +253,16 @@ class PredictionRunner:
                 raise error
             except Exception:
                 log.error("caught exception while running prediction", exc_info=True)
-                if self._shutdown_event is not None:
+                if (
+                    isinstance(error, FatalWorkerException)
+                    and self._max_worker_restarts > 0
+                ):
+                    self._restart_worker()
+                elif self._shutdown_event is not None:
                     self._shutdown_event.set()
 
         self._response = event_handler.response
+        self._event_handler = event_handler
         self._result = self._threadpool.apply_async(
             func=predict,
             kwds={
@@ -152,6 +270,7 @@ class PredictionRunner:
                 "request": prediction,
                 "event_handler": event_handler,
                 "should_cancel": self._should_cancel,
+                "trace_id": trace_id,
             },
             callback=cleanup,
             error_callback=handle_error,
@@ -159,36 +278,221 @@ class PredictionRunner:
 
         return (self._response, self._result)
 
+    def _restart_worker(self) -> None:
+        now = time.monotonic()
+        while (
+            self._worker_restarts
+            and now - self._worker_restarts[0] > self._worker_restart_window
+        ):
+            self._worker_restarts.popleft()
+
+        self._worker.terminate()
+        if self._response is not None:
+            self._remember_completed(self._response)
+        self._response = None
+        self._event_handler = None
+
+        if len(self._worker_restarts) >= self._max_worker_restarts:
+            log.error(
+                "worker crashed too many times, not restarting it",
+                restarts=len(self._worker_restarts),
+                window=self._worker_restart_window,
+            )
+            task = self._threadpool.apply_async(func=worker_restarts_exhausted)
+        else:
+            log.info("restarting worker after crash")
+            self._worker_restarts.append(now)
+            self._worker = self._make_worker()
+            task = self._start_setup()
+
+        self._result = task
+        if self._on_worker_restart is not None:
+            self._on_worker_restart(task)
+
+    def reload(self) -> SetupTask:
+        """
+        Replaces the worker with a new one, which imports the predictor's code
+        again, and runs setup on it.
+        """
+        if self.is_busy():
+            raise RunnerBusyError()
+
+        log.info("reloading predictor")
+        self._worker.terminate()
+        self._worker = self._make_worker()
+        self._result = self._start_setup()
+        return self._result
+
+    def setup_logs(self) -> str:
+        return "".join(self._setup_logs)
+
     def is_busy(self) -> bool:
         if self._result is None:
             return False
 
         if not self._result.ready():
+            if self._slot_release_overdue():
+                self._poison_slot()
             return True
 
+        if self._response is not None:
+            self._remember_completed(self._response)
         self._response = None
+        self._event_handler = None
         self._result = None
         return False
 
+    def _slot_release_overdue(self) -> bool:
+        response = self._response
+        if response is None or response.completed_at is None:
+            return False
+        if self._worker.is_defunct():
+            return False
+        elapsed = datetime.now(tz=timezone.utc) - response.completed_at
+        return elapsed.total_seconds() > self._slot_release_timeout
+
+    def _poison_slot(self) -> None:
+        assert self._response is not None
+        log.warn(
+            "prediction completed but its slot was never released, giving up on it",
+            prediction_id=self._response.id,
+            slot_release_timeout=self._slot_release_timeout,
+        )
+        # The prediction thread is stuck, so there's no way to run anything
+        # else. Mark the slot as defunct and shut down, so that the server is
+        # restarted rather than staying busy forever.
+        self._worker.terminate()
+        if self._shutdown_event is not None:
+            self._shutdown_event.set()
+
+    def _remember_completed(self, response: schema.PredictionResponse) -> None:
+        predict_time = None
+        if response.started_at is not None and response.completed_at is not None:
+            predict_time = (response.completed_at - response.started_at).total_seconds()
+        self._recent.append(
+            {
+                "id": response.id,
+                "status": response.status,
+                "predict_time": predict_time,
+                "error": response.error,
+            }
+        )
+
+        if response.id is None:
+            return
+        self._completed.pop(response.id, None)
+        self._completed[response.id] = (time.monotonic(), response)
+        self._expire_completed()
+
+    def _expire_completed(self) -> None:
+        now = time.monotonic()
+        while self._completed:
+            oldest_id, (completed_at, _) = next(iter(self._completed.items()))
+            if (
+                len(self._completed) <= self._max_completed_predictions
+                and now - completed_at <= self._completed_prediction_ttl
+            ):
+                break
+            del self._completed[oldest_id]
+
+    def completed_prediction(
+        self, prediction_id: str
+    ) -> Optional[schema.PredictionResponse]:
+        """
+        Returns the result of a recently completed prediction, if the runner
+        still has it.
+        """
+        self.is_busy()
+        self._expire_completed()
+        if prediction_id not in self._completed:
+            return None
+        _, response = self._completed[prediction_id]
+        if not schema.Status.is_terminal(response.status):
+            return None
+        return response
+
+    def recent_predictions(self) -> List[Dict[str, Any]]:
+        """
+        Returns a summary of the most recently completed predictions, newest
+        first.
+        """
+        self.is_busy()
+        return list(reversed(self._recent))
+
+    def wait_for_idle(self, timeout: float) -> bool:
+        """
+        Waits up to timeout seconds for the running prediction, if any, to
+        finish. Returns whether the runner has no prediction running. This
+        only reads the runner's state, so it's safe to call from any thread.
+        """
+        deadline = time.monotonic() + timeout
+        while True:
+            result = self._result
+            if self._response is None or result is None or result.ready():
+                return True
+            remaining = deadline - time.monotonic()
+            if remaining <= 0:
+                return False
+            result.wait(remaining)
+
+    def slot_stats(self) -> SlotStats:
+        # There's only ever one slot. It's busy while setup or a prediction is
+        # running, and defunct if its worker has died and won't be restarted.
+        busy = self.is_busy()
+        if self._worker.is_defunct():
+            return SlotStats(total=1, idle=0, busy=0, defunct=1)
+        if busy:
+            return SlotStats(total=1, idle=0, busy=1, defunct=0)
+        return SlotStats(total=1, idle=1, busy=0, defunct=0)
+
+    def worker_rss_bytes(self) -> Optional[int]:
+        pid = self._worker.pid()
+        if pid is None or self._worker.is_defunct():
+            return None
+        return rss_bytes(pid)
+
+    def current_prediction(self) -> Optional[schema.PredictionResponse]:
+        if not self.is_busy():
+            return None
+        return self._response
+
+    def get_prediction(self, prediction_id: str) -> schema.PredictionResponse:
+        self.is_busy()
+        if self._response is not None and self._response.id == prediction_id:
+            return self._response
+        self._expire_completed()
+        if prediction_id in self._completed:
+            _, response = self._completed[prediction_id]
+            return response
+        raise UnknownPredictionError()
+
     def shutdown(self) -> None:
         self._worker.terminate()
         self._threadpool.terminate()
         self._threadpool.join()
 
-    def cancel(self, prediction_id: Optional[str] = None) -> None:
+    def cancel(
+        self, prediction_id: Optional[str] = None, reason: Optional[str] = None
+    ) -> None:
         if not self.is_busy():
             return
         assert self._response is not None
         if prediction_id is not None and prediction_id != self._response.id:
             raise UnknownPredictionError()
+        if reason is not None and self._event_handler is not None:
+            self._event_handler.cancel_reason = reason
         self._should_cancel.set()
 
 
 def create_event_handler(
     prediction: schema.PredictionRequest,
     upload_url: Optional[str] = None,
+    metrics: Optional[Metrics] = None,
 ) -> "PredictionEventHandler":
     response = schema.PredictionResponse(**prediction.dict())
+    warnings = get_deprecation_warnings(prediction.input)
+    if warnings:
+        response.warnings = warnings
 
     webhook = prediction.webhook
     events_filter = (
@@ -198,13 +502,28 @@ def create_event_handler(
     webhook_sender = None
     if webhook is not None:
         webhook_sender = webhook_caller_filtered(webhook, set(events_filter))
+        # The event handler moves the prediction to processing straight away,
+        # so this is the only chance to say it's been accepted.
+        if schema.WebhookEvent.STARTING in events_filter:
+            starting = response.copy(update={"status": schema.Status.STARTING})
+            webhook_sender(
+                jsonable_encoder(starting.dict(exclude_unset=True)),
+                schema.WebhookEvent.STARTING,
+            )
 
     file_uploader = None
     if upload_url is not None:
         file_uploader = generate_file_uploader(upload_url, prediction_id=prediction.id)
 
     event_handler = PredictionEventHandler(
-        response, webhook_sender=webhook_sender, file_uploader=file_uploader
+        response,
+        webhook_sender=webhook_sender,
+        file_uploader=file_uploader,
+        metrics=metrics,
+        max_logs_size=_max_logs_size,
+        max_log_line_length=(
+            int(_max_log_line_length) if _max_log_line_length else None
+        ),
     )
 
     return event_handler
@@ -232,6 +551,9 @@ class PredictionEventHandler:
         p: schema.PredictionResponse,
         webhook_sender: Optional[Callable[[Any, schema.WebhookEvent], None]] = None,
         file_uploader: Optional[Callable[[Any], Any]] = None,
+        metrics: Optional[Metrics] = None,
+        max_logs_size: Optional[int] = None,
+        max_log_line_length: Optional[int] = None,
     ) -> None:
         log.info("starting prediction")
         self.p = p
@@ -240,8 +562,24 @@ class PredictionEventHandler:
         self.p.logs = ""
         self.p.started_at = datetime.now(tz=timezone.utc)
 
+        # Predictions queued upstream carry the time they were created.
+        # Otherwise they've only been waiting since they were submitted here.
+        self._queued_at = self.p.created_at or self.p.started_at
+        if self._queued_at.tzinfo is None:
+            self._queued_at = self._queued_at.replace(tzinfo=timezone.utc)
+
         self._webhook_sender = webhook_sender
         self._file_uploader = file_uploader
+        self._metrics = metrics
+        self._max_logs_size = max_logs_size
+        self._max_log_line_length = max_log_line_length
+        self._logs_truncated = False
+
+        # Why the prediction was canceled, if it is, e.g. as given by the
+        # client that canceled it.
+        self.cancel_reason: Optional[str] = None
+        if self._metrics is not None:
+            self._metrics.prediction_started()
 
         # HACK: don't send an initial webhook if we're trying to optimize for
         # latency (this guarantees that the first output webhook won't be
@@ -269,9 +607,36 @@ class PredictionEventHandler:
 
     def append_logs(self, logs: str) -> None:
         assert self.p.logs is not None
+        # Once the logs have been truncated, later logs are dropped.
+        if self._logs_truncated:
+            return
+        if self._max_log_line_length is not None:
+            logs = _truncate_lines(logs, self._max_log_line_length)
+        if (
+            self._max_logs_size is not None
+            and len(self.p.logs) + len(logs) > self._max_logs_size
+        ):
+            logs = logs[: self._max_logs_size - len(self.p.logs)]
+            logs += LOGS_TRUNCATED_MARKER
+            self._logs_truncated = True
         self.p.logs += logs
         self._send_webhook(schema.WebhookEvent.LOGS)
 
+    def set_response_header(self, name: str, value: str) -> None:
+        # The predictor checks this too, but it's checked again here in case
+        # it was sent some other way.
+        try:
+            validate_response_header(name, value)
+        except ValueError as e:
+            log.warn("ignoring response header from predictor", error=str(e))
+            return
+        self.p.response_headers[name] = value
+
+    def set_metric(self, name: str, value: Any) -> None:
+        if self.p.metrics is None:
+            self.p.metrics = {}
+        self.p.metrics[name] = value
+
     def succeeded(self) -> None:
         log.info("prediction succeeded")
         self.p.status = schema.Status.SUCCEEDED
@@ -280,27 +645,48 @@ class PredictionEventHandler:
         # that...
         assert self.p.completed_at is not None
         assert self.p.started_at is not None
+        time_in_queue = (self.p.started_at - self._queued_at).total_seconds()
         self.p.metrics = {
-            "predict_time": (self.p.completed_at - self.p.started_at).total_seconds()
+            # Metrics recorded by the predictor, which can't override ours.
+            **(self.p.metrics or {}),
+            "predict_time": (self.p.completed_at - self.p.started_at).total_seconds(),
+            # Clocks upstream may be skewed, so never report a negative wait.
+            "time_in_queue": max(time_in_queue, 0.0),
         }
+        self._record_completion()
         self._send_webhook(schema.WebhookEvent.COMPLETED)
 
-    def failed(self, error: str) -> None:
+    def failed(self, error: str, error_traceback: Optional[str] = None) -> None:
         log.info("prediction failed", error=error)
         self.p.status = schema.Status.FAILED
         self.p.error = error
+        if error_traceback is not None:
+            self.p.traceback = error_traceback
         self._set_completed_at()
+        self._record_completion()
         self._send_webhook(schema.WebhookEvent.COMPLETED)
 
     def canceled(self) -> None:
-        log.info("prediction canceled")
+        log.info("prediction canceled", reason=self.cancel_reason)
         self.p.status = schema.Status.CANCELED
+        if self.cancel_reason is not None:
+            self.p.cancel_reason = self.cancel_reason
         self._set_completed_at()
-        self._send_webhook(schema.WebhookEvent.COMPLETED)
+        self._record_completion()
+        self._send_webhook(schema.WebhookEvent.CANCELED)
 
     def _set_completed_at(self) -> None:
         self.p.completed_at = datetime.now(tz=timezone.utc)
 
+    def _record_completion(self) -> None:
+        if self._metrics is None:
+            return
+        assert self.p.status is not None
+        assert self.p.completed_at is not None
+        assert self.p.started_at is not None
+        predict_time = (self.p.completed_at - self.p.started_at).total_seconds()
+        self._metrics.prediction_completed(self.p.status, predict_time)
+
     def _send_webhook(self, event: schema.WebhookEvent) -> None:
         if self._webhook_sender is not None:
             dict_response = jsonable_encoder(self.response.dict(exclude_unset=True))
@@ -320,26 +706,59 @@ class PredictionEventHandler:
             raise FileUploadError("Got error trying to upload output files") from error
 
 
-def setup(*, worker: Worker) -> SetupResult:
-    logs = []
+def _truncate_lines(logs: str, max_length: int) -> str:
+    lines = []
+    for line in logs.splitlines(keepends=True):
+        content = line.rstrip("\r\n")
+        if len(content) > max_length:
+            line = content[:max_length] + "..." + line[len(content) :]
+        lines.append(line)
+    return "".join(lines)
+
+
+def setup(
+    *,
+    worker: Worker,
+    gpu_wait_timeout: Optional[float] = None,
+    logs: Optional[List[str]] = None,
+) -> SetupResult:
+    # Logs are appended to the list passed in, if any, so that they can be read
+    # while setup is still running.
+    if logs is None:
+        logs = []
     status = None
     started_at = datetime.now(tz=timezone.utc)
 
-    try:
-        for event in worker.setup():
-            if isinstance(event, Log):
-                logs.append(event.message)
-            elif isinstance(event, Done):
-                status = (
-                    schema.Status.FAILED if event.error else schema.Status.SUCCEEDED
-                )
-    except Exception:
-        logs.append(traceback.format_exc())
-        status = schema.Status.FAILED
+    # Don't start the model until it can see a GPU, otherwise it would load
+    # onto the CPU or fail, and never pick up the GPU once it does appear.
+    if gpu_wait_timeout is not None and not wait_for_gpu(gpu_wait_timeout):
+        logs.append(
+            f"Error: no GPU became available within {gpu_wait_timeout} seconds\n"
+        )
+        return SetupResult(
+            started_at=started_at,
+            completed_at=datetime.now(tz=timezone.utc),
+            logs="".join(logs),
+            status=schema.Status.FAILED,
+        )
 
-    if status is None:
-        logs.append("Error: did not receive 'done' event from setup!")
-        status = schema.Status.FAILED
+    with span("setup") as attributes:
+        try:
+            for event in worker.setup():
+                if isinstance(event, Log):
+                    logs.append(event.message)
+                elif isinstance(event, Done):
+                    status = (
+                        schema.Status.FAILED if event.error else schema.Status.SUCCEEDED
+                    )
+        except Exception:
+            logs.append(traceback.format_exc())
+            status = schema.Status.FAILED
+
+        if status is None:
+            logs.append("Error: did not receive 'done' event from setup!")
+            status = schema.Status.FAILED
+        attributes["status"] = status.value
 
     completed_at = datetime.now(tz=timezone.utc)
 
@@ -356,29 +775,62 @@ def setup(*, worker: Worker) -> SetupResult:
     )
 
 
+def wait_for_gpu(timeout: float) -> bool:
+    deadline = time.monotonic() + timeout
+    waiting = False
+    while not gpu_available():
+        if time.monotonic() >= deadline:
+            log.error("timed out waiting for a GPU", timeout=timeout)
+            return False
+        if not waiting:
+            log.info("waiting for a GPU to become available before running setup")
+            waiting = True
+        time.sleep(GPU_POLL_INTERVAL)
+    return True
+
+
+def worker_restarts_exhausted() -> SetupResult:
+    now = datetime.now(tz=timezone.utc)
+    return SetupResult(
+        started_at=now,
+        completed_at=now,
+        logs="Error: worker crashed too many times, not restarting it\n",
+        status=schema.Status.FAILED,
+    )
+
+
 def predict(
     *,
     worker: Worker,
     request: schema.PredictionRequest,
     event_handler: PredictionEventHandler,
     should_cancel: threading.Event,
+    trace_id: Optional[str] = None,
 ) -> schema.PredictionResponse:
     # Set up logger context within prediction thread.
     structlog.contextvars.clear_contextvars()
-    structlog.contextvars.bind_contextvars(prediction_id=request.id)
-
-    try:
-        return _predict(
-            worker=worker,
-            request=request,
-            event_handler=event_handler,
-            should_cancel=should_cancel,
-        )
-    except Exception as e:
-        tb = traceback.format_exc()
-        event_handler.append_logs(tb)
-        event_handler.failed(error=str(e))
-        raise
+    structlog.contextvars.bind_contextvars(
+        prediction_id=request.id, slot_index=SLOT_INDEX
+    )
+    if trace_id is not None:
+        structlog.contextvars.bind_contextvars(trace_id=trace_id)
+
+    with span("predict", prediction_id=request.id, trace_id=trace_id) as attributes:
+        try:
+            return _predict(
+                worker=worker,
+                request=request,
+                event_handler=event_handler,
+                should_cancel=should_cancel,
+            )
+        except Exception as e:
+            tb = traceback.format_exc()
+            event_handler.append_logs(tb)
+            event_handler.failed(error=str(e))
+            raise
+        finally:
+            status = event_handler.response.status
+            attributes["status"] = status.value if status is not None else None
 
 
 def _predict(
@@ -398,12 +850,15 @@ def _predict(
             # Check if v is an instance of URLPath
             if isinstance(v, types.URLPath):
                 input_dict[k] = v.convert()
-            # Check if v is a list of URLPath instances
+            elif isinstance(v, types.Base64Bytes):
+                input_dict[k] = v.to_bytes()
+            # Check if v is a list of URLPath instances, which may be
+            # interspersed with None for List[Optional[Path]] inputs
             elif isinstance(v, list) and all(
-                isinstance(item, types.URLPath) for item in v
+                isinstance(item, types.URLPath) or item is None for item in v
             ):
-                input_dict[k] = [item.convert() for item in v]
-        except requests.exceptions.RequestException as e:
+                input_dict[k] = [item.convert() if item else None for item in v]
+        except (requests.exceptions.RequestException, types.InputDownloadError) as e:
             tb = traceback.format_exc()
             event_handler.append_logs(tb)
             event_handler.failed(error=str(e))
@@ -426,6 +881,12 @@ def _predict(
         elif isinstance(event, Log):
             event_handler.append_logs(event.message)
 
+        elif isinstance(event, PredictionMetric):
+            event_handler.set_metric(event.name, event.value)
+
+        elif isinstance(event, PredictionResponseHeader):
+            event_handler.set_response_header(event.name, event.value)
+
         elif isinstance(event, PredictionOutputType):
             if output_type is not None:
                 event_handler.failed(error="Predictor returned unexpected output")
@@ -448,7 +909,10 @@ def _predict(
             if event.canceled:
                 event_handler.canceled()
             elif event.error:
-                event_handler.failed(error=str(event.error_detail))
+                event_handler.failed(
+                    error=str(event.error_detail),
+                    error_traceback=event.error_traceback or None,
+                )
             else:
                 event_handler.succeeded()
 
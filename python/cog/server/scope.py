@@ -0,0 +1,75 @@
+import contextlib
+from typing import Any, Callable, Iterator, Optional
+
+_metric_recorder: Optional[Callable[[str, Any], None]] = None
+_response_header_setter: Optional[Callable[[str, str], None]] = None
+
+# Predictors can only set headers that describe the response, so they can't
+# interfere with how the server's responses are framed or authenticated.
+ALLOWED_RESPONSE_HEADERS = {
+    "cache-control",
+    "content-disposition",
+    "content-language",
+    "etag",
+    "expires",
+    "last-modified",
+    "vary",
+}
+
+
+def record_metric(name: str, value: Any) -> None:
+    """
+    Records a custom metric for the running prediction. It's merged into the
+    prediction's `metrics`, alongside `predict_time`.
+
+    Does nothing when called outside of a prediction, e.g. when a predictor is
+    called directly in a test.
+    """
+    if _metric_recorder is not None:
+        _metric_recorder(name, value)
+
+
+@contextlib.contextmanager
+def metric_recorder(recorder: Callable[[str, Any], None]) -> Iterator[None]:
+    global _metric_recorder
+
+    _metric_recorder = recorder
+    try:
+        yield
+    finally:
+        _metric_recorder = None
+
+
+def set_response_header(name: str, value: str) -> None:
+    """
+    Sets a header on the HTTP response of the running prediction, if it was
+    requested synchronously. Only the headers in ALLOWED_RESPONSE_HEADERS can
+    be set.
+
+    Does nothing when called outside of a prediction, e.g. when a predictor is
+    called directly in a test.
+    """
+    validate_response_header(name, value)
+    if _response_header_setter is not None:
+        _response_header_setter(name, value)
+
+
+def validate_response_header(name: str, value: str) -> None:
+    if name.lower() not in ALLOWED_RESPONSE_HEADERS:
+        allowed = ", ".join(sorted(ALLOWED_RESPONSE_HEADERS))
+        raise ValueError(
+            f"Predictors can't set the {name!r} header. Allowed headers are: {allowed}"
+        )
+    if "\r" in value or "\n" in value:
+        raise ValueError(f"The value of the {name!r} header can't contain newlines")
+
+
+@contextlib.contextmanager
+def response_header_setter(setter: Callable[[str, str], None]) -> Iterator[None]:
+    global _response_header_setter
+
+    _response_header_setter = setter
+    try:
+        yield
+    finally:
+        _response_header_setter = None
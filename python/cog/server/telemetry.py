@@ -1,10 +1,17 @@
+import time
 from contextlib import contextmanager
 from contextvars import ContextVar
-from typing import Generator, Optional
+from datetime import datetime, timezone
+from typing import Any, Callable, Dict, Generator, List, Optional
+
+import structlog
+from attrs import define
 
 # TypedDict was added in 3.8
 from typing_extensions import TypedDict
 
+log = structlog.get_logger(__name__)
+
 
 # See: https://www.w3.org/TR/trace-context/
 class TraceContext(TypedDict, total=False):
@@ -40,6 +47,25 @@ def current_trace_context() -> Optional[TraceContext]:
     return TRACE_CONTEXT.get()
 
 
+def get_trace_id(ctx: Optional[TraceContext]) -> Optional[str]:
+    """
+    Returns the trace ID from the traceparent header in a trace context, or
+    None if there isn't a valid one.
+    """
+    if not ctx or "traceparent" not in ctx:
+        return None
+    # traceparent is "<version>-<trace-id>-<parent-id>-<flags>"
+    parts = ctx["traceparent"].split("-")
+    if len(parts) < 4:
+        return None
+    trace_id = parts[1].lower()
+    if len(trace_id) != 32 or trace_id == "0" * 32:
+        return None
+    if any(c not in "0123456789abcdef" for c in trace_id):
+        return None
+    return trace_id
+
+
 @contextmanager
 def trace_context(ctx: TraceContext) -> Generator[None, None, None]:
     """
@@ -52,3 +78,53 @@ def trace_context(ctx: TraceContext) -> Generator[None, None, None]:
         yield
     finally:
         TRACE_CONTEXT.reset(t)
+
+
+@define
+class Span:
+    name: str
+    attributes: Dict[str, Any]
+    start_time: datetime
+    duration: float
+
+
+SpanListener = Callable[[Span], None]
+
+_span_listeners: List[SpanListener] = []
+
+
+def add_span_listener(listener: SpanListener) -> None:
+    """
+    Registers a function to be called with every span once it ends, e.g. to
+    export spans to OpenTelemetry. Cog doesn't export spans itself.
+    """
+    _span_listeners.append(listener)
+
+
+def remove_span_listener(listener: SpanListener) -> None:
+    _span_listeners.remove(listener)
+
+
+@contextmanager
+def span(name: str, **attributes: Any) -> Generator[Dict[str, Any], None, None]:
+    """
+    Times the block it wraps as a named span, and passes it to the span
+    listeners when it ends. Yields the span's attributes, so that more can be
+    added once they're known.
+    """
+    start_time = datetime.now(tz=timezone.utc)
+    start = time.perf_counter()
+    try:
+        yield attributes
+    finally:
+        ended = Span(
+            name=name,
+            attributes=attributes,
+            start_time=start_time,
+            duration=time.perf_counter() - start,
+        )
+        for listener in list(_span_listeners):
+            try:
+                listener(ended)
+            except Exception:
+                log.warn("span listener failed", span=name, exc_info=True)
@@ -1,10 +1,16 @@
+import base64
+import gzip
+import hashlib
+import hmac
+import json
 import os
-from typing import Any, Callable, Set
+import random
+import threading
+import time
+from typing import Any, Callable, Dict, Optional, Set, Tuple
 
 import requests
 import structlog
-from requests.adapters import HTTPAdapter
-from requests.packages.urllib3.util.retry import Retry  # type: ignore
 
 from ..schema import Status, WebhookEvent
 from .response_throttler import ResponseThrottler
@@ -20,6 +26,39 @@ _response_interval = float(os.environ.get("COG_THROTTLE_RESPONSE_INTERVAL", 0.5)
 # are latency sensitive.
 SKIP_START_EVENT = _response_interval < 0.1
 
+# Terminal webhooks are retried with exponential backoff. With the defaults,
+# delivery is attempted for up to roughly 200 seconds, providing resilience
+# through temporary networking and availability issues.
+_max_attempts = int(os.environ.get("COG_WEBHOOK_MAX_ATTEMPTS", 12))
+_retry_backoff_factor = 0.1
+_retry_backoff_max = 120.0
+_retry_status_codes = {429, 500, 502, 503, 504}
+
+# Terminal webhooks that are still being retried, so that shutdown can wait
+# for them rather than dropping them with the process.
+_pending_retries: Set[threading.Thread] = set()
+_pending_retries_lock = threading.Lock()
+
+# When enabled, webhooks only include the logs written since the previous
+# webhook was sent, rather than all logs so far.
+_log_deltas = os.environ.get("COG_WEBHOOK_LOG_DELTAS", "").lower() in ("1", "true")
+
+
+def _parse_gzip_min_size(value: Optional[str]) -> Optional[int]:
+    if not value:
+        return None
+    if not value.isdigit():
+        raise ValueError(
+            f"COG_WEBHOOK_GZIP_MIN_SIZE must be a number of bytes, not {value!r}"
+        )
+    return int(value)
+
+
+# When set, webhook bodies of at least this many bytes are gzip-compressed.
+# It's checked when the server starts, rather than when the first webhook is
+# sent.
+_gzip_min_size = _parse_gzip_min_size(os.environ.get("COG_WEBHOOK_GZIP_MIN_SIZE"))
+
 
 def webhook_caller_filtered(
     webhook: str,
@@ -30,6 +69,13 @@ def webhook_caller_filtered(
     def caller(response: Any, event: WebhookEvent) -> None:
         if event in webhook_events_filter:
             upstream_caller(response)
+        # Cancellation used to be reported as a completed event, so clients
+        # that filter for completed events still hear about it.
+        elif (
+            event == WebhookEvent.CANCELED
+            and WebhookEvent.COMPLETED in webhook_events_filter
+        ):
+            upstream_caller(response)
 
     return caller
 
@@ -39,25 +85,173 @@ def webhook_caller(webhook: str) -> Callable[[Any], None]:
     # for every prediction.
     throttler = ResponseThrottler(response_interval=_response_interval)
 
-    default_session = requests_session()
-    retry_session = requests_session_with_retries()
+    session = requests_session()
+    signing_secret = os.environ.get("WEBHOOK_SIGNING_SECRET")
+    superseded: Optional[threading.Event] = None
+    log_cursor = 0
+    # Updates are sent one at a time, in the order they were made, even if
+    # they come from different threads. Each takes a ticket and waits for
+    # its turn, as threads waiting on a lock can acquire it in any order.
+    turn = threading.Condition()
+    next_ticket = 0
+    now_serving = 0
 
     def caller(response: Any) -> None:
+        nonlocal next_ticket, now_serving
+        with turn:
+            ticket = next_ticket
+            next_ticket += 1
+            while ticket != now_serving:
+                turn.wait()
+        try:
+            send(response)
+        finally:
+            with turn:
+                now_serving += 1
+                turn.notify_all()
+
+    def send(response: Any) -> None:
+        nonlocal superseded, log_cursor
+
         if throttler.should_send_response(response):
+            if "logs" in response:
+                response, log_cursor = _with_log_cursor(response, log_cursor)
+            body, headers = encode_webhook_payload(
+                response,
+                signing_secret,
+                gzip_min_size=_gzip_min_size,
+            )
             if Status.is_terminal(response["status"]):
-                # For terminal updates, retry persistently
-                retry_session.post(webhook, json=response)
+                # A newer terminal update supersedes any earlier one that is
+                # still being retried.
+                if superseded is not None:
+                    superseded.set()
+                superseded = threading.Event()
+
+                # For terminal updates, retry persistently. Retries happen in
+                # the background so they don't hold up the prediction.
+                if not _post_webhook(session, webhook, body, headers):
+                    thread = threading.Thread(
+                        target=_retry_webhook,
+                        args=(session, webhook, body, headers, superseded),
+                        daemon=True,
+                    )
+                    with _pending_retries_lock:
+                        _pending_retries.add(thread)
+                    thread.start()
             else:
                 # For other requests, don't retry, and ignore any errors
-                try:
-                    default_session.post(webhook, json=response)
-                except requests.exceptions.RequestException:
-                    log.warn("caught exception while sending webhook", exc_info=True)
-            throttler.update_last_sent_response_time()
+                _post_webhook(session, webhook, body, headers)
+            throttler.update_last_sent_response_time(response["status"])
 
     return caller
 
 
+def _with_log_cursor(response: Any, last_cursor: int) -> Tuple[Any, int]:
+    """
+    Adds a `log_cursor` to a webhook payload: the offset into the prediction's
+    logs up to which the payload is complete. In delta mode, the logs before
+    the last cursor sent are left out.
+    """
+    logs = response["logs"] or ""
+    cursor = len(logs)
+    response = {**response, "log_cursor": cursor}
+    if _log_deltas:
+        response["logs"] = logs[last_cursor:]
+    return response, cursor
+
+
+def _post_webhook(
+    session: requests.Session, webhook: str, body: bytes, headers: Dict[str, str]
+) -> bool:
+    """
+    Makes a single attempt to send a webhook, returning False if the request
+    failed in a way that is worth retrying.
+    """
+    try:
+        resp = session.post(webhook, data=body, headers=headers)
+    except requests.exceptions.RequestException:
+        log.warn("caught exception while sending webhook", exc_info=True)
+        return False
+    return resp.status_code not in _retry_status_codes
+
+
+def _retry_webhook(
+    session: requests.Session,
+    webhook: str,
+    body: bytes,
+    headers: Dict[str, str],
+    superseded: threading.Event,
+) -> None:
+    try:
+        for attempt in range(1, _max_attempts):
+            if superseded.wait(_retry_delay(attempt)):
+                log.info("webhook superseded by a newer update, no longer retrying")
+                return
+            if _post_webhook(session, webhook, body, headers):
+                return
+        log.error("failed to send webhook", attempts=_max_attempts)
+    finally:
+        with _pending_retries_lock:
+            _pending_retries.discard(threading.current_thread())
+
+
+def wait_for_pending_webhooks(timeout: float) -> bool:
+    """
+    Waits up to timeout seconds for terminal webhooks that are still being
+    retried to be delivered or given up on. Returns whether they all were.
+    """
+    deadline = time.monotonic() + timeout
+    with _pending_retries_lock:
+        threads = list(_pending_retries)
+    for thread in threads:
+        thread.join(max(0.0, deadline - time.monotonic()))
+    with _pending_retries_lock:
+        return not _pending_retries
+
+
+def _retry_delay(attempt: int) -> float:
+    delay = min(_retry_backoff_factor * (2 ** (attempt - 1)), _retry_backoff_max)
+    # Add jitter, so that many failing webhooks don't all retry in lockstep.
+    return random.uniform(delay / 2, delay)  # noqa: S311
+
+
+def encode_webhook_payload(
+    response: Any,
+    signing_secret: Optional[str] = None,
+    gzip_min_size: Optional[int] = None,
+) -> Tuple[bytes, Dict[str, str]]:
+    """
+    Serializes a webhook payload, returning the request body and headers. If a
+    signing secret is given, the body is signed and the signature attached in
+    the `webhook-signature` header. If gzip_min_size is given, bodies at least
+    that big are compressed after they're signed.
+    """
+    body = json.dumps(response).encode("utf-8")
+    headers = {"content-type": "application/json"}
+    if signing_secret:
+        timestamp = str(int(time.time()))
+        headers["webhook-timestamp"] = timestamp
+        headers["webhook-signature"] = sign_webhook_payload(
+            signing_secret, timestamp, body
+        )
+    if gzip_min_size is not None and len(body) >= gzip_min_size:
+        body = gzip.compress(body)
+        headers["content-encoding"] = "gzip"
+    return body, headers
+
+
+def sign_webhook_payload(secret: str, timestamp: str, body: bytes) -> str:
+    """
+    Computes an HMAC-SHA256 signature over the timestamp and body of a webhook.
+    The timestamp is part of the signed content so that receivers can reject
+    replayed requests.
+    """
+    signed_content = timestamp.encode("utf-8") + b"." + body
+    digest = hmac.new(secret.encode("utf-8"), signed_content, hashlib.sha256).digest()
+    return "v1," + base64.b64encode(digest).decode("utf-8")
+
+
 def requests_session() -> requests.Session:
     session = requests.Session()
     session.headers["user-agent"] = (
@@ -73,22 +267,3 @@ def requests_session() -> requests.Session:
         session.headers["authorization"] = "Bearer " + auth_token
 
     return session
-
-
-def requests_session_with_retries() -> requests.Session:
-    # This session will retry requests up to 12 times, with exponential
-    # backoff. In total it'll try for up to roughly 320 seconds, providing
-    # resilience through temporary networking and availability issues.
-    session = requests_session()
-    adapter = HTTPAdapter(
-        max_retries=Retry(
-            total=12,
-            backoff_factor=0.1,
-            status_forcelist=[429, 500, 502, 503, 504],
-            allowed_methods=["POST"],
-        )
-    )
-    session.mount("http://", adapter)
-    session.mount("https://", adapter)
-
-    return session
@@ -1,23 +1,43 @@
+import asyncio
+import contextlib
+import inspect
 import multiprocessing
+import multiprocessing.spawn
 import os
+import pathlib
+import pickle
+import shutil
 import signal
 import sys
+import tempfile
 import traceback
 import types
 from enum import Enum, auto, unique
 from multiprocessing.connection import Connection
-from typing import Any, Dict, Iterable, Optional, TextIO, Union
+from multiprocessing.reduction import ForkingPickler
+from typing import Any, Dict, Iterable, Iterator, Optional, TextIO, Union
 
+from ..files import temp_dir
 from ..json import make_encodeable
-from ..predictor import BasePredictor, get_predict, load_predictor_from_ref, run_setup
+from ..predictor import (
+    BasePredictor,
+    get_predict,
+    load_predictor_from_ref,
+    run_setup,
+    run_warmup,
+)
+from ..types import Path as CogPath
 from .eventtypes import (
     Done,
     Heartbeat,
     Log,
     PredictionInput,
+    PredictionMetric,
     PredictionOutput,
     PredictionOutputType,
+    PredictionResponseHeader,
     Shutdown,
+    SpilledPredictionOutput,
 )
 from .exceptions import (
     CancelationException,
@@ -25,10 +45,26 @@ from .exceptions import (
     InvalidStateException,
 )
 from .helpers import StreamRedirector, WrappedStream
+from .scope import metric_recorder, response_header_setter
 
 _spawn = multiprocessing.get_context("spawn")
 
-_PublicEventType = Union[Done, Heartbeat, Log, PredictionOutput, PredictionOutputType]
+# Outputs that are larger than this once pickled are written to a temporary
+# file by the child process, rather than being sent through the pipe.
+DEFAULT_MAX_INLINE_OUTPUT_SIZE = 6 * 1024 * 1024
+
+# Connection frames each message with a signed 32-bit length.
+_MAX_MESSAGE_SIZE = 0x7FFFFFFF
+
+_PublicEventType = Union[
+    Done,
+    Heartbeat,
+    Log,
+    PredictionMetric,
+    PredictionOutput,
+    PredictionOutputType,
+    PredictionResponseHeader,
+]
 
 
 @unique
@@ -41,19 +77,42 @@ class WorkerState(Enum):
 
 
 class Worker:
-    def __init__(self, predictor_ref: str, tee_output: bool = True) -> None:
+    def __init__(
+        self,
+        predictor_ref: str,
+        tee_output: bool = True,
+        isolate_cwd: bool = False,
+        max_inline_output_size: int = DEFAULT_MAX_INLINE_OUTPUT_SIZE,
+        python_executable: Optional[str] = None,
+    ) -> None:
+        if not 0 < max_inline_output_size <= _MAX_MESSAGE_SIZE:
+            raise ValueError(
+                f"max_inline_output_size must be between 1 and {_MAX_MESSAGE_SIZE} bytes"
+            )
+
         self._state = WorkerState.NEW
         self._allow_cancel = False
 
         # A pipe with which to communicate with the child worker.
         self._events, child_events = _spawn.Pipe()
-        self._child = _ChildWorker(predictor_ref, child_events, tee_output)
+        self._child = _ChildWorker(
+            predictor_ref,
+            child_events,
+            tee_output,
+            isolate_cwd=isolate_cwd,
+            max_inline_output_size=max_inline_output_size,
+        )
         self._terminating = False
 
+        # The interpreter to run the child with, e.g. a wrapper script that
+        # activates another environment. Defaults to this process's own.
+        self._python_executable = python_executable or os.environ.get("COG_PYTHON")
+
     def setup(self) -> Iterable[_PublicEventType]:
         self._assert_state(WorkerState.NEW)
         self._state = WorkerState.STARTING
-        self._child.start()
+        with _spawn_executable(self._python_executable):
+            self._child.start()
 
         return self._wait(raise_on_error="Predictor errored during setup")
 
@@ -87,6 +146,21 @@ class Worker:
             self._child.terminate()
             self._child.join()
 
+    def pid(self) -> Optional[int]:
+        """
+        Returns the PID of the child process, or None if it hasn't started.
+        """
+        return self._child.pid
+
+    def is_defunct(self) -> bool:
+        """
+        Returns whether the worker can no longer run predictions, because it
+        was terminated or its child process has exited.
+        """
+        if self._state == WorkerState.DEFUNCT:
+            return True
+        return self._state != WorkerState.NEW and not self._child.is_alive()
+
     def cancel(self) -> None:
         if (
             self._allow_cancel
@@ -120,11 +194,18 @@ class Worker:
                 continue
 
             ev = self._events.recv()
+            if isinstance(ev, SpilledPredictionOutput):
+                ev = _load_spilled_output(ev.path)
             yield ev
 
             if isinstance(ev, Done):
                 done = ev
 
+        # Anything a dead child sent that we haven't read is lost, but large
+        # outputs it spilled to files still need removing.
+        if not self._child.is_alive():
+            self._discard_spilled_outputs()
+
         if done:
             if done.error and raise_on_error:
                 raise FatalWorkerException(raise_on_error + ": " + done.error_detail)
@@ -140,6 +221,33 @@ class Worker:
                 f"Prediction failed for an unknown reason. It might have run out of memory? (exitcode {exitcode})"
             )
 
+    def _discard_spilled_outputs(self) -> None:
+        with contextlib.suppress(EOFError, OSError):
+            while self._events.poll():
+                ev = self._events.recv()
+                if isinstance(ev, SpilledPredictionOutput):
+                    with contextlib.suppress(FileNotFoundError):
+                        os.unlink(ev.path)
+
+
+@contextlib.contextmanager
+def _spawn_executable(executable: Optional[str]) -> Iterator[None]:
+    """
+    Spawns child processes with the given Python executable while the context
+    is active. The executable is global to multiprocessing, so it's restored
+    afterwards.
+    """
+    if executable is None:
+        yield
+        return
+
+    previous = multiprocessing.spawn.get_executable()
+    _spawn.set_executable(executable)
+    try:
+        yield
+    finally:
+        _spawn.set_executable(previous)
+
 
 class _ChildWorker(_spawn.Process):  # type: ignore
     def __init__(
@@ -147,13 +255,20 @@ class _ChildWorker(_spawn.Process):  # type: ignore
         predictor_ref: str,
         events: Connection,
         tee_output: bool = True,
+        isolate_cwd: bool = False,
+        max_inline_output_size: int = DEFAULT_MAX_INLINE_OUTPUT_SIZE,
     ) -> None:
         self._predictor_ref = predictor_ref
         self._predictor: Optional[BasePredictor] = None
         self._events = events
         self._tee_output = tee_output
+        self._isolate_cwd = isolate_cwd
+        self._max_inline_output_size = max_inline_output_size
         self._cancelable = False
-        self._events_lock = _spawn.Lock()
+        # Reentrant, because metrics can be recorded from inside a generator
+        # while its outputs are being sent.
+        self._events_lock = _spawn.RLock()
+        self._loop: Optional[asyncio.AbstractEventLoop] = None
 
         super().__init__()
 
@@ -188,6 +303,8 @@ class _ChildWorker(_spawn.Process):  # type: ignore
             # Could be a function or a class
             if hasattr(self._predictor, "setup"):
                 run_setup(self._predictor)
+            if hasattr(self._predictor, "warmup"):
+                run_warmup(self._predictor)
         except Exception as e:
             traceback.print_exc()
             done.error = True
@@ -220,33 +337,140 @@ class _ChildWorker(_spawn.Process):  # type: ignore
         self._cancelable = True
         try:
             predict = get_predict(self._predictor)
-            result = predict(**payload)
-
-            if result:
-                with self._events_lock:
-                    if isinstance(result, types.GeneratorType):
-                        self._events.send(PredictionOutputType(multi=True))
-                        for r in result:
-                            self._events.send(
-                                PredictionOutput(payload=make_encodeable(r))
-                            )
-                    else:
-                        self._events.send(PredictionOutputType(multi=False))
-                        self._events.send(
-                            PredictionOutput(payload=make_encodeable(result))
-                        )
+
+            # Generators run lazily as we iterate over them, so the working
+            # directory has to stay in place until all output has been sent.
+            with self._prediction_cwd(), metric_recorder(
+                self._send_metric
+            ), response_header_setter(self._send_response_header):
+                result = predict(**payload)
+                if inspect.isawaitable(result):
+                    result = self._run_async(result)
+
+                if result:
+                    with self._events_lock:
+                        if isinstance(result, types.GeneratorType):
+                            self._events.send(PredictionOutputType(multi=True))
+                            for r in result:
+                                self._send_output(self._encode_output(r))
+                        elif isinstance(result, types.AsyncGeneratorType):
+                            self._events.send(PredictionOutputType(multi=True))
+                            for r in self._iterate_async(result):
+                                self._send_output(self._encode_output(r))
+                        else:
+                            self._events.send(PredictionOutputType(multi=False))
+                            self._send_output(self._encode_output(result))
         except CancelationException:
             done.canceled = True
         except Exception as e:
             traceback.print_exc()
             done.error = True
             done.error_detail = str(e)
+            done.error_traceback = traceback.format_exc()
         finally:
             self._cancelable = False
         self._stream_redirector.drain()
         with self._events_lock:
             self._events.send(done)
 
+    def _run_async(self, awaitable: Any) -> Any:
+        if self._loop is None:
+            self._loop = asyncio.new_event_loop()
+            asyncio.set_event_loop(self._loop)
+
+        task = asyncio.ensure_future(awaitable, loop=self._loop)
+        try:
+            return self._loop.run_until_complete(task)
+        except CancelationException:
+            # The cancelation signal may have interrupted the event loop
+            # rather than the task, in which case the task is still pending
+            # and has to be stopped too.
+            if not task.done():
+                task.cancel()
+                with contextlib.suppress(BaseException):
+                    self._loop.run_until_complete(task)
+            raise
+
+    def _iterate_async(self, agen: Any) -> Iterator[Any]:
+        """
+        Steps through an async generator one item at a time, so that outputs
+        are sent as they're produced and cancelation stops the generator
+        partway through.
+        """
+        try:
+            while True:
+                try:
+                    yield self._run_async(agen.__anext__())
+                except StopAsyncIteration:
+                    return
+        finally:
+            self._run_async(agen.aclose())
+
+    @contextlib.contextmanager
+    def _prediction_cwd(self) -> Iterator[None]:
+        """
+        If enabled, runs the prediction in a fresh temporary working directory,
+        so that relative paths written by concurrent predictions don't collide.
+        The original working directory is restored afterwards, and the
+        temporary one is removed.
+        """
+        if not self._isolate_cwd:
+            yield
+            return
+
+        original_cwd = os.getcwd()
+        cwd = tempfile.mkdtemp(prefix="cog-predict-", dir=temp_dir())
+        os.chdir(cwd)
+        try:
+            yield
+        finally:
+            os.chdir(original_cwd)
+            shutil.rmtree(cwd, ignore_errors=True)
+
+    def _encode_output(self, output: Any) -> Any:
+        output = make_encodeable(output)
+        if self._isolate_cwd:
+            # The parent process resolves output paths against its own working
+            # directory, so relative paths need to be made absolute while we
+            # are still inside the prediction's working directory.
+            output = _absolute_paths(output)
+            # That directory is removed once the prediction finishes, which
+            # may be before the parent process has uploaded the files in it.
+            output = _move_files_out_of(output, os.getcwd())
+        # Fail with a clear error now, rather than when the parent process
+        # tries to read the file to upload it.
+        _check_output_files(output)
+        return output
+
+    def _send_output(self, payload: Any) -> None:
+        data = ForkingPickler.dumps(PredictionOutput(payload=payload))
+        with self._events_lock:
+            if len(data) <= self._max_inline_output_size:
+                self._events.send_bytes(data)
+                return
+
+            fd, path = tempfile.mkstemp(
+                prefix="cog-output-", suffix=".pickle", dir=temp_dir()
+            )
+            try:
+                with os.fdopen(fd, "wb") as f:
+                    f.write(data)
+                self._events.send(SpilledPredictionOutput(path=path))
+            except BaseException:
+                # e.g. the prediction was canceled before the file was sent,
+                # so the parent process will never read it.
+                with contextlib.suppress(OSError):
+                    os.unlink(path)
+                raise
+
+    def _send_metric(self, name: str, value: Any) -> None:
+        with self._events_lock:
+            self._events.send(PredictionMetric(name=name, value=value))
+
+    def _send_response_header(self, name: str, value: str) -> None:
+        with self._events_lock:
+            self._events.send(PredictionResponseHeader(name=name, value=value))
+
     def _signal_handler(self, signum: int, frame: Optional[types.FrameType]) -> None:
         if signum == signal.SIGUSR1 and self._cancelable:
             raise CancelationException()
@@ -259,3 +483,64 @@ class _ChildWorker(_spawn.Process):  # type: ignore
             original_stream.flush()
         with self._events_lock:
             self._events.send(Log(data, source=stream_name))
+
+
+def _load_spilled_output(path: str) -> PredictionOutput:
+    try:
+        with open(path, "rb") as f:
+            # This was written by our own child process.
+            return pickle.load(f)  # noqa: S301
+    finally:
+        os.unlink(path)
+
+
+def _absolute_paths(obj: Any) -> Any:
+    if isinstance(obj, dict):
+        return {key: _absolute_paths(value) for key, value in obj.items()}
+    if isinstance(obj, list):
+        return [_absolute_paths(value) for value in obj]
+    if isinstance(obj, CogPath) and not obj.is_absolute():
+        path = type(obj)(os.path.abspath(obj))
+        path.content_type = obj.content_type
+        return path
+    if isinstance(obj, pathlib.Path) and not obj.is_absolute():
+        return type(obj)(os.path.abspath(obj))
+    return obj
+
+
+def _move_files_out_of(obj: Any, directory: str) -> Any:
+    if isinstance(obj, dict):
+        return {k: _move_files_out_of(v, directory) for k, v in obj.items()}
+    if isinstance(obj, list):
+        return [_move_files_out_of(value, directory) for value in obj]
+    directory = os.path.realpath(directory)
+    if not isinstance(obj, pathlib.Path) or not obj.exists():
+        return obj
+    source = os.path.abspath(obj)
+    if os.path.commonpath([directory, source]) == directory:
+        # This moves directories too, which are zipped when they're uploaded.
+        # Keep the name, as it's used for the uploaded file's name.
+        destination = os.path.join(
+            tempfile.mkdtemp(prefix="cog-output-", dir=temp_dir()),
+            os.path.basename(source),
+        )
+        shutil.move(source, destination)
+        path = type(obj)(destination)
+        if isinstance(obj, CogPath):
+            path.content_type = obj.content_type
+        return path
+    return obj
+
+
+def _check_output_files(obj: Any) -> None:
+    if isinstance(obj, dict):
+        for value in obj.values():
+            _check_output_files(value)
+    elif isinstance(obj, list):
+        for value in obj:
+            _check_output_files(value)
+    elif isinstance(obj, CogPath):
+        if not obj.exists():
+            raise ValueError(f"Predictor returned a path that does not exist: {obj}")
+        if not os.access(obj, os.R_OK):
+            raise ValueError(f"Predictor returned a path that cannot be read: {obj}")
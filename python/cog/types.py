@@ -1,3 +1,6 @@
+import base64
+import binascii
+import functools
 import io
 import mimetypes
 import os
@@ -6,12 +9,25 @@ import shutil
 import tempfile
 import urllib.parse
 import urllib.request
-from typing import Any, Dict, Iterator, List, Optional, TypeVar, Union
+from typing import (
+    Any,
+    AsyncIterator,
+    Callable,
+    Dict,
+    Iterator,
+    List,
+    Optional,
+    TypeVar,
+    Union,
+)
 
 import requests
+import urllib3
 from pydantic import Field, SecretStr
 from typing_extensions import NotRequired, TypedDict
 
+from .files import temp_dir
+
 FILENAME_ILLEGAL_CHARS = set("\u0000/")
 
 # Linux allows files up to 255 bytes long. We enforce a slightly shorter
@@ -37,6 +53,16 @@ class CogBuildConfig(TypedDict, total=False):
     run: Optional[Union[List[str], List[Dict[str, Any]]]]
 
 
+# The empty defaults that Input(default_factory=...) can stand for. Inputs are
+# sent as JSON, so empty sets and tuples are empty lists.
+DEFAULT_FACTORIES: Dict[Any, Callable[[], Any]] = {
+    list: list,
+    tuple: list,
+    set: list,
+    dict: dict,
+}
+
+
 def Input(
     default: Any = ...,
     description: str = None,
@@ -44,19 +70,57 @@ def Input(
     le: float = None,
     min_length: int = None,
     max_length: int = None,
+    min_items: int = None,
+    max_items: int = None,
     regex: str = None,
     choices: List[Union[str, int]] = None,
+    default_factory: Callable[[], Any] = None,
+    when: Dict[str, Any] = None,
+    deprecated: bool = None,
+    title: str = None,
+    example: Any = None,
+    format: str = None,
 ) -> Any:
     """Input is similar to pydantic.Field, but doesn't require a default value to be the first argument."""
+    if default_factory is not None:
+        if default is not ...:
+            raise TypeError("Input() can't have both a default and a default_factory")
+        if default_factory not in DEFAULT_FACTORIES:
+            raise TypeError(
+                f"Unsupported default_factory {default_factory!r}. Only list, dict, set and tuple are supported."
+            )
+        default = DEFAULT_FACTORIES[default_factory]()
+    # Conditions are only for UIs to decide when to show an input, so they're
+    # passed through to the schema as they are, and aren't enforced.
+    extra: Dict[str, Any] = {}
+    if when is not None:
+        if not isinstance(when, dict) or not isinstance(when.get("field"), str):
+            raise TypeError(
+                f"Input(when=...) must be a dict naming a field, like {{'field': 'mode', 'equals': 'advanced'}}, not {when!r}"
+            )
+        extra["x-cog-when"] = when
+    # Deprecated inputs still work, but predictions that use them get a warning.
+    if deprecated:
+        extra["deprecated"] = True
+    # These are passed through to the schema as they are, for user interfaces.
+    # A format set by the input's type, like "uri" for Path, takes precedence.
+    if example is not None:
+        extra["example"] = example
+    if format is not None:
+        extra["format"] = format
     return Field(
         default,
+        title=title,
         description=description,
         ge=ge,
         le=le,
         min_length=min_length,
         max_length=max_length,
+        min_items=min_items,
+        max_items=max_items,
         regex=regex,
         choices=choices,
+        **extra,
     )
 
 
@@ -73,6 +137,36 @@ class Secret(SecretStr):
         )
 
 
+class Base64Bytes(str):
+    """
+    Base64Bytes validates a `bytes` input. It stays a base64 string so that the
+    input can be echoed back in responses, and is decoded just before it's
+    passed to predict().
+    """
+
+    @classmethod
+    def __get_validators__(cls) -> Iterator[Any]:
+        yield cls.validate
+
+    @classmethod
+    def validate(cls, value: Any) -> "Base64Bytes":
+        if not isinstance(value, str):
+            raise TypeError("value is not a base64-encoded string")
+        try:
+            base64.b64decode(value, validate=True)
+        except binascii.Error as e:
+            raise ValueError("value is not valid base64") from e
+        return cls(value)
+
+    def to_bytes(self) -> bytes:
+        return base64.b64decode(self)
+
+    @classmethod
+    def __modify_schema__(cls, field_schema: Dict[str, Any]) -> None:
+        """Defines what this type should be in openapi.json"""
+        field_schema.update({"type": "string", "format": "byte"})
+
+
 class File(io.IOBase):
     """Deprecated: use Path instead."""
 
@@ -89,13 +183,14 @@ class File(io.IOBase):
 
         parsed_url = urllib.parse.urlparse(value)
         if parsed_url.scheme == "data":
-            res = urllib.request.urlopen(value)  # noqa: S310
+            res = _open_data_url(value)
             return io.BytesIO(res.read())
-        elif parsed_url.scheme == "http" or parsed_url.scheme == "https":
+        elif parsed_url.scheme in _download_backends:
             return URLFile(value)
         else:
+            supported = ", ".join(f"'{s}'" for s in ["data", *_download_backends])
             raise ValueError(
-                f"'{parsed_url.scheme}' is not a valid URL scheme. 'data', 'http', or 'https' is supported."
+                f"'{parsed_url.scheme}' is not a valid URL scheme. {supported} are supported."
             )
 
     @classmethod
@@ -108,6 +203,26 @@ class File(io.IOBase):
 class Path(pathlib.PosixPath):
     validate_always = True
 
+    # When set, outputs are uploaded with this content type rather than one
+    # guessed from the file's extension.
+    content_type: Optional[str] = None
+
+    def with_content_type(self, content_type: str) -> "Path":
+        """
+        Returns a copy of this path that's output with the given content type.
+        """
+        path = type(self)(self)
+        path.content_type = content_type
+        return path
+
+    def __reduce__(self) -> Any:
+        # Outputs are pickled to send them from the worker, and pathlib
+        # doesn't know about the content type.
+        reduced = super().__reduce__()
+        if self.content_type is None:
+            return reduced
+        return (*reduced, {"content_type": self.content_type})
+
     @classmethod
     def __get_validators__(cls) -> Iterator[Any]:
         yield cls.validate
@@ -150,7 +265,9 @@ class URLPath(pathlib.PosixPath):
 
     def convert(self) -> Path:
         if self._path is None:
-            dest = tempfile.NamedTemporaryFile(suffix=self.filename, delete=False)
+            dest = tempfile.NamedTemporaryFile(
+                suffix=self.filename, dir=temp_dir(), delete=False
+            )
             shutil.copyfileobj(self.fileobj, dest)
             self._path = Path(dest.name)
         return self._path
@@ -214,11 +331,10 @@ class URLFile(io.IOBase):
             return object.__getattribute__(self, "__target__")
         except AttributeError:
             url = object.__getattribute__(self, "__url__")
-            resp = requests.get(url, stream=True)
-            resp.raise_for_status()
-            resp.raw.decode_content = True
-            object.__setattr__(self, "__target__", resp.raw)
-            return resp.raw
+            scheme = urllib.parse.urlparse(url).scheme
+            target = _download_backends[scheme](url)
+            object.__setattr__(self, "__target__", target)
+            return target
 
     def __repr__(self) -> str:
         try:
@@ -231,11 +347,169 @@ class URLFile(io.IOBase):
             return f"<{type(self).__name__} at 0x{id(self):x} wrapping {target!r}>"
 
 
+def register_download_backend(scheme: str, backend: Callable[[str], Any]) -> None:
+    """
+    Registers a function that opens URLs with the given scheme, returning a
+    readable file-like object. File and Path inputs with this scheme are
+    downloaded using it.
+    """
+    _download_backends[scheme] = backend
+
+
+# The timeout applies to connecting and to each read, rather than to the whole
+# download, so large files that are downloading steadily aren't cut off.
+DEFAULT_INPUT_DOWNLOAD_TIMEOUT = 60.0
+
+
+class InputDownloadError(requests.exceptions.RequestException):
+    """Raised when a URL passed as an input can't be downloaded."""
+
+
+def _open_http_url(url: str) -> Any:
+    timeout = float(
+        os.environ.get("COG_INPUT_DOWNLOAD_TIMEOUT", DEFAULT_INPUT_DOWNLOAD_TIMEOUT)
+    )
+    max_size = os.environ.get("COG_MAX_INPUT_DOWNLOAD_SIZE")
+    limit = int(max_size) if max_size else None
+
+    resp = requests.get(url, stream=True, timeout=timeout)
+    resp.raise_for_status()
+    content_length = resp.headers.get("content-length")
+    if limit is not None and content_length and int(content_length) > limit:
+        resp.close()
+        raise InputDownloadError(f"{url} is larger than the limit of {limit} bytes")
+    resp.raw.decode_content = True
+    return io.BufferedReader(_DownloadReader(resp.raw, url, limit))
+
+
+class _DownloadReader(io.RawIOBase):
+    """
+    Reads a download, failing if it turns out to be larger than the limit, e.g.
+    because the server didn't send a Content-Length.
+    """
+
+    def __init__(self, raw: Any, url: str, limit: Optional[int]) -> None:
+        self._raw = raw
+        self._url = url
+        self._limit = limit
+        self._size = 0
+
+    def readable(self) -> bool:
+        return True
+
+    def readinto(self, buffer: Any) -> int:
+        try:
+            data = self._raw.read(len(buffer))
+        except urllib3.exceptions.HTTPError as e:
+            # e.g. a read timing out
+            raise InputDownloadError(f"Failed to download {self._url}: {e}") from e
+        self._size += len(data)
+        if self._limit is not None and self._size > self._limit:
+            raise InputDownloadError(
+                f"{self._url} is larger than the limit of {self._limit} bytes"
+            )
+        buffer[: len(data)] = data
+        return len(data)
+
+    def close(self) -> None:
+        self._raw.close()
+        super().close()
+
+
+def _wrap_download_errors(open_url: Callable[[str], Any]) -> Callable[[str], Any]:
+    """
+    Turns any error from a download backend, whether opening the URL or
+    reading from it, into an InputDownloadError. SDKs like boto3 raise their
+    own exceptions for e.g. a missing object or missing credentials, and those
+    should fail the prediction rather than the server.
+    """
+
+    @functools.wraps(open_url)
+    def wrapped(url: str) -> Any:
+        try:
+            return io.BufferedReader(_BackendReader(open_url(url), url))
+        except InputDownloadError:
+            raise
+        except Exception as e:
+            raise InputDownloadError(f"Failed to download {url}: {e}") from e
+
+    return wrapped
+
+
+class _BackendReader(io.RawIOBase):
+    def __init__(self, raw: Any, url: str) -> None:
+        self._raw = raw
+        self._url = url
+
+    def readable(self) -> bool:
+        return True
+
+    def readinto(self, buffer: Any) -> int:
+        try:
+            data = self._raw.read(len(buffer))
+        except Exception as e:
+            raise InputDownloadError(f"Failed to download {self._url}: {e}") from e
+        buffer[: len(data)] = data
+        return len(data)
+
+    def close(self) -> None:
+        self._raw.close()
+        super().close()
+
+
+@_wrap_download_errors
+def _open_s3_url(url: str) -> Any:
+    try:
+        import boto3  # type: ignore
+    except ImportError as e:
+        raise ValueError("boto3 must be installed to download s3:// URLs") from e
+
+    parsed_url = urllib.parse.urlparse(url)
+    obj = boto3.client("s3").get_object(
+        Bucket=parsed_url.netloc, Key=parsed_url.path.lstrip("/")
+    )
+    return obj["Body"]
+
+
+@_wrap_download_errors
+def _open_gs_url(url: str) -> Any:
+    try:
+        from google.cloud import storage  # type: ignore
+    except ImportError as e:
+        raise ValueError(
+            "google-cloud-storage must be installed to download gs:// URLs"
+        ) from e
+
+    parsed_url = urllib.parse.urlparse(url)
+    bucket = storage.Client().bucket(parsed_url.netloc)
+    return bucket.blob(parsed_url.path.lstrip("/")).open("rb")
+
+
+def _open_data_url(url: str) -> Any:
+    try:
+        return urllib.request.urlopen(url)  # noqa: S310
+    except ValueError as e:
+        # e.g. a missing comma, or invalid base64
+        raise ValueError(f"'{_truncate(url)}' is not a valid data URL: {e}") from e
+
+
+def _truncate(s: str, length: int = 50) -> str:
+    return s if len(s) <= length else s[:length] + "..."
+
+
+_download_backends: Dict[str, Callable[[str], Any]] = {
+    "http": _open_http_url,
+    "https": _open_http_url,
+    "s3": _open_s3_url,
+    "gs": _open_gs_url,
+}
+
+
 def get_filename(url: str) -> str:
     parsed_url = urllib.parse.urlparse(url)
 
     if parsed_url.scheme == "data":
-        resp = urllib.request.urlopen(url)  # noqa: S310
+        resp = _open_data_url(url)
         mime_type = resp.headers.get_content_type()
         extension = mimetypes.guess_extension(mime_type)
         if extension is None:
@@ -285,6 +559,21 @@ class ConcatenateIterator(Iterator[Item]):
         return value
 
 
+class AsyncConcatenateIterator(AsyncIterator[Item]):
+    @classmethod
+    def __modify_schema__(cls, field_schema: Dict[str, Any]) -> None:
+        """Defines what this type should be in openapi.json"""
+        ConcatenateIterator.__modify_schema__(field_schema)
+
+    @classmethod
+    def __get_validators__(cls) -> Iterator[Any]:
+        yield cls.validate
+
+    @classmethod
+    def validate(cls, value: AsyncIterator[Any]) -> AsyncIterator[Any]:
+        return value
+
+
 def _len_bytes(s: str, encoding: str = "utf-8") -> int:
     return len(s.encode(encoding))
 
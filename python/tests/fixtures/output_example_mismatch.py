@@ -0,0 +1,8 @@
+from cog import BasePredictor
+
+COG_OUTPUT_EXAMPLE = "not a number"
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> int:
+        return 42
@@ -54,6 +54,7 @@ def make_client(
     fixture_name: str,
     upload_url: Optional[str] = None,
     additional_config: Optional[dict] = None,
+    **kwargs,
 ):
     """
     Creates a fastapi test client for an app that uses the requested Predictor.
@@ -67,6 +68,7 @@ def make_client(
         config=config,
         shutdown_event=threading.Event(),
         upload_url=upload_url,
+        **kwargs,
     )
     return TestClient(app)
 
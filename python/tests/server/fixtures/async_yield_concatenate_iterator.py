@@ -0,0 +1,10 @@
+from cog import AsyncConcatenateIterator, BasePredictor
+
+
+class Predictor(BasePredictor):
+    async def predict(
+        self,
+    ) -> AsyncConcatenateIterator[str]:
+        predictions = ["foo", "bar", "baz"]
+        for prediction in predictions:
+            yield prediction
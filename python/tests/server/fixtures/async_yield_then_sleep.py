@@ -0,0 +1,11 @@
+import asyncio
+from typing import AsyncIterator
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    async def predict(self, sleep: float = 0) -> AsyncIterator[str]:
+        yield "first"
+        await asyncio.sleep(sleep)
+        yield "second"
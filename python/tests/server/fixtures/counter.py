@@ -0,0 +1,10 @@
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def setup(self):
+        self.count = 0
+
+    def predict(self) -> int:
+        self.count += 1
+        return self.count
@@ -0,0 +1,11 @@
+import os
+import signal
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(self, crash: bool = False) -> str:
+        if crash:
+            os.kill(os.getpid(), signal.SIGKILL)
+        return "ok"
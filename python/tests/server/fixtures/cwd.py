@@ -0,0 +1,10 @@
+import os
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> str:
+        with open("output.txt", "w") as fh:
+            fh.write("hello")
+        return os.getcwd()
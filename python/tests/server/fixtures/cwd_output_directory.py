@@ -0,0 +1,11 @@
+import os
+
+from cog import BasePredictor, Path
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> Path:
+        os.mkdir("images")
+        with open("images/1.txt", "w") as fh:
+            fh.write("hello")
+        return Path("images")
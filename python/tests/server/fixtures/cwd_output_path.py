@@ -0,0 +1,8 @@
+from cog import BasePredictor, Path
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> Path:
+        with open("output.txt", "w") as fh:
+            fh.write("hello")
+        return Path("output.txt")
@@ -0,0 +1,17 @@
+import functools
+
+from cog import BasePredictor, Input
+
+
+def traced(f):
+    @functools.wraps(f)
+    def wrapper(*args, **kwargs):
+        return f(*args, **kwargs)
+
+    return wrapper
+
+
+class Predictor(BasePredictor):
+    @traced
+    def predict(self, text: str = Input(description="Text to repeat")) -> str:
+        return text
@@ -0,0 +1,34 @@
+import functools
+
+from cog import BasePredictor, Input
+
+
+def traced(f):
+    @functools.wraps(f)
+    def wrapper(*args, **kwargs):
+        return f(*args, **kwargs)
+
+    return wrapper
+
+
+def retried(times):
+    def decorator(f):
+        @functools.wraps(f)
+        def wrapper(*args, **kwargs):
+            for _ in range(times - 1):
+                try:
+                    return f(*args, **kwargs)
+                except Exception:
+                    pass
+            return f(*args, **kwargs)
+
+        return wrapper
+
+    return decorator
+
+
+class Predictor(BasePredictor):
+    @traced
+    @retried(times=3)
+    def predict(self, text: str = Input(description="Text to repeat")) -> str:
+        return text
@@ -0,0 +1,12 @@
+from typing_extensions import Annotated
+
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        prompt: Annotated[str, Input(description="A prompt")],
+        steps: Annotated[int, Input(ge=1)] = 20,
+    ) -> str:
+        return f"{prompt} {steps}"
@@ -0,0 +1,14 @@
+from typing import List
+
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        count: int = Input(default=5, ge=1, le=10),
+        text: str = Input(default="hello", min_length=2, max_length=5),
+        items: List[str] = Input(default=["a"], min_length=1, max_length=3),
+        code: str = Input(default="abc", regex=r"^[a-z]+$"),
+    ) -> str:
+        return f"{count} {text} {len(items)}"
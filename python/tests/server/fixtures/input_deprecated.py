@@ -0,0 +1,10 @@
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        text: str = Input(default="hello"),
+        style: str = Input(default="plain", deprecated=True),
+    ) -> str:
+        return f"{text} ({style})"
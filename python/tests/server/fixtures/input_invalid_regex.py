@@ -0,0 +1,6 @@
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(self, code: str = Input(regex="[a-z")) -> str:
+        return code
@@ -0,0 +1,8 @@
+from typing import List
+
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(self, tags: List[str] = Input(min_items=2, max_items=3)) -> str:
+        return ",".join(tags)
@@ -0,0 +1,6 @@
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(self, num: int, ratio: float, flag: bool) -> str:
+        return f"{num!r} {ratio!r} {flag!r}"
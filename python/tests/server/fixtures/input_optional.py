@@ -0,0 +1,12 @@
+from typing import Optional
+
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        text: str = Input(description="Required, as it has no default"),
+        suffix: Optional[str] = Input(default=None),
+    ) -> str:
+        return text + (suffix or "")
@@ -0,0 +1,8 @@
+from typing import Optional
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(self, num: Optional[int] = None) -> str:
+        return f"{type(num).__name__} {num}"
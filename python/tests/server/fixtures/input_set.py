@@ -0,0 +1,8 @@
+from typing import Set
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(self, tags: Set[str]) -> str:
+        return f"{type(tags).__name__}: {','.join(sorted(tags))}"
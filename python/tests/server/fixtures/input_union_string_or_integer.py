@@ -0,0 +1,10 @@
+from typing import Optional, Union
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self, value: Union[str, int], other: Optional[Union[str, int]] = None
+    ) -> str:
+        return f"{type(value).__name__} {value} {type(other).__name__}"
@@ -0,0 +1,11 @@
+from cog import BasePredictor, Input, Secret
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        scale: float = Input(default=1.5),
+        seed: int = Input(default=0, example=42),
+        token: Secret = Input(default="hunter2"),
+    ) -> str:
+        return f"{scale} {seed}"
@@ -0,0 +1,18 @@
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        text: str,
+        count: int = Input(default=1, description="How many times to repeat it"),
+    ) -> str:
+        """
+        Repeats some text.
+
+        Args:
+            text (str): The text to
+                repeat.
+            count: Not used, as Input() describes it.
+        """
+        return text * count
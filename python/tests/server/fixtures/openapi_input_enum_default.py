@@ -0,0 +1,20 @@
+from enum import Enum
+
+from cog import BasePredictor, Input
+
+
+class Quality(str, Enum):
+    LOW = "low"
+    HIGH = "high"
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        quality: str = Input(
+            default=Quality.HIGH.value,
+            choices=[Quality.LOW.value, Quality.HIGH.value],
+        ),
+        preset: str = Quality.LOW.name,
+    ) -> str:
+        return f"{quality} {preset}"
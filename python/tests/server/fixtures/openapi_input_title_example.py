@@ -0,0 +1,10 @@
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        prompt: str = Input(title="Prompt text", example="an onion", format="textarea"),
+        seed: int = Input(default=0, title="Random seed"),
+    ) -> str:
+        return f"{prompt} {seed}"
@@ -0,0 +1,10 @@
+from cog import BasePredictor, Input
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        mode: str = Input(default="simple", choices=["simple", "advanced"]),
+        steps: int = Input(default=20, when={"field": "mode", "equals": "advanced"}),
+    ) -> str:
+        return f"{mode} {steps}"
@@ -0,0 +1,12 @@
+from typing import List
+
+from cog import BasePredictor
+
+COG_OUTPUT_EXAMPLE = ["hello", "world"]
+
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+    ) -> List[str]:
+        pass
@@ -0,0 +1,15 @@
+from __future__ import annotations
+
+from cog import BasePredictor
+from pydantic import BaseModel
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> "MyOutput":
+        return MyOutput(foo_number=1)
+
+
+# Defined after the predictor, so predict() has to refer to it by name
+class MyOutput(BaseModel):
+    foo_number: int = 42
+    foo_string: str = "meaning of life"
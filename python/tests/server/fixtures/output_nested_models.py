@@ -0,0 +1,17 @@
+from cog import BasePredictor
+from pydantic import BaseModel
+
+
+class Point(BaseModel):
+    x: int
+    y: int
+
+
+class Box(BaseModel):
+    top_left: Point
+    bottom_right: Point
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> Box:
+        return Box(top_left=Point(x=0, y=0), bottom_right=Point(x=1, y=1))
@@ -0,0 +1,13 @@
+import os
+import tempfile
+
+from cog import BasePredictor, Path
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> Path:
+        temp_dir = tempfile.mkdtemp()
+        temp_path = os.path.join(temp_dir, "image")
+        with open(temp_path, "wb") as fh:
+            fh.write(b"hello")
+        return Path(temp_path).with_content_type("image/png")
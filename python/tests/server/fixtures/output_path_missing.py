@@ -0,0 +1,10 @@
+import os
+import tempfile
+
+from cog import BasePredictor, Path
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> Path:
+        temp_dir = tempfile.mkdtemp()
+        return Path(os.path.join(temp_dir, "never-written.txt"))
@@ -0,0 +1,14 @@
+import os
+import tempfile
+from typing import Tuple
+
+from cog import BasePredictor, Path
+
+
+class Predictor(BasePredictor):
+    def predict(self) -> Tuple[Path, str]:
+        temp_dir = tempfile.mkdtemp()
+        temp_path = os.path.join(temp_dir, "file.txt")
+        with open(temp_path, "w") as fh:
+            fh.write("hello")
+        return Path(temp_path), "caption"
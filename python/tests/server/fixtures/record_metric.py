@@ -0,0 +1,12 @@
+from typing import Iterator
+
+from cog import BasePredictor, record_metric
+
+
+class Predictor(BasePredictor):
+    def predict(self, steps: int = 2) -> Iterator[str]:
+        record_metric("input_token_count", steps)
+        for i in range(steps):
+            yield f"step {i}"
+            # Recorded while outputs are being sent, between two of them.
+            record_metric("steps_completed", i + 1)
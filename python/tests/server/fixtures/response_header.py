@@ -0,0 +1,7 @@
+from cog import BasePredictor, set_response_header
+
+
+class Predictor(BasePredictor):
+    def predict(self, header: str = "Cache-Control") -> str:
+        set_response_header(header, "max-age=60")
+        return "hello"
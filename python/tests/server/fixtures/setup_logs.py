@@ -0,0 +1,15 @@
+import time
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def setup(self):
+        print("downloading weights")
+        time.sleep(0.5)
+        print("loading model")
+        time.sleep(0.5)
+        print("ready")
+
+    def predict(self) -> str:
+        return "hello"
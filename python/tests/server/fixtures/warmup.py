@@ -0,0 +1,14 @@
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def setup(self):
+        print("setting up")
+        self.warmed_up = False
+
+    def warmup(self):
+        print("warming up")
+        self.warmed_up = True
+
+    def predict(self) -> bool:
+        return self.warmed_up
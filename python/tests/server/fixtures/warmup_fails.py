@@ -0,0 +1,9 @@
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def warmup(self):
+        raise RuntimeError("out of memory")
+
+    def predict(self) -> str:
+        return "still works"
@@ -0,0 +1,11 @@
+import time
+from typing import Iterator
+
+from cog import BasePredictor
+
+
+class Predictor(BasePredictor):
+    def predict(self, count: int = 1000) -> Iterator[int]:
+        for i in range(count):
+            time.sleep(0.01)
+            yield i
@@ -0,0 +1,36 @@
+import asyncio
+import json
+
+import pytest
+from cog.server.body_limit import BodySizeLimitMiddleware
+
+
+async def _app(scope, receive, send):
+    raise AssertionError("request should have been rejected")
+
+
+@pytest.mark.parametrize("content_length", [b"abc", b"10, 10", b"-1", b""])
+def test_malformed_content_length_is_rejected(content_length):
+    middleware = BodySizeLimitMiddleware(
+        _app, max_body_size=100, paths=("/predictions",)
+    )
+    scope = {
+        "type": "http",
+        "method": "POST",
+        "path": "/predictions",
+        "headers": [(b"content-length", content_length)],
+    }
+    sent = []
+
+    async def receive():
+        return {"type": "http.request", "body": b"", "more_body": False}
+
+    async def send(message):
+        sent.append(message)
+
+    asyncio.run(middleware(scope, receive, send))
+
+    assert sent[0]["status"] == 400
+    assert json.loads(sent[1]["body"]) == {
+        "detail": "Content-Length header is not valid"
+    }
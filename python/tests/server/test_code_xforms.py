@@ -76,3 +76,43 @@ class Predictor(BasePredictor):
 """
     assert expected_source.strip() == new_source.strip()
     assert code_xforms.load_module_from_string(uuid.uuid4().hex, new_source)
+
+
+@pytest.mark.skipif(sys.version_info < (3, 9), reason="requires python3.9 or higher")
+def test_predict_keeps_output_example():
+    with open(
+        f"{g_module_dir}/fixtures/openapi_output_example.py", encoding="utf-8"
+    ) as file:
+        source_code = file.read()
+
+    new_source = code_xforms.strip_model_source_code(
+        source_code, "Predictor", "predict"
+    )
+    module = code_xforms.load_module_from_string(uuid.uuid4().hex, new_source)
+    assert module.COG_OUTPUT_EXAMPLE == ["hello", "world"]
+
+
+@pytest.mark.skipif(sys.version_info < (3, 9), reason="requires python3.9 or higher")
+@pytest.mark.parametrize(
+    "fixture_name", ["decorated_predict", "decorated_predict_stacked"]
+)
+def test_predict_strips_decorators(fixture_name):
+    with open(f"{g_module_dir}/fixtures/{fixture_name}.py", encoding="utf-8") as file:
+        source_code = file.read()
+
+    new_source = code_xforms.strip_model_source_code(
+        source_code, "Predictor", "predict"
+    )
+    expected_source = """
+import functools
+from cog import BasePredictor, Input
+
+
+
+class Predictor(BasePredictor):
+
+    def predict(self, text: str=Input(description='Text to repeat')) -> str:
+        return None
+"""
+    assert expected_source.strip() == new_source.strip()
+    assert code_xforms.load_module_from_string(uuid.uuid4().hex, new_source)
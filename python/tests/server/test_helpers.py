@@ -176,6 +176,43 @@ def test_stream_redirector_line_handling(tmpfile, writes, expected_output):
     r.shutdown()
 
 
+def test_stream_redirector_replaces_invalid_utf8(tmpfile):
+    filename = tmpfile()
+    fake_stream = open(filename, "w")
+    ws = WrappedStream("fake_stream", fake_stream)
+    output = []
+
+    def _write_hook(stream_name, original_stream, data):
+        output.append((stream_name, data))
+
+    ws.wrap()
+    r = StreamRedirector([ws], _write_hook)
+    r.start()
+
+    # e.g. a native library writing Latin-1
+    os.write(fake_stream.fileno(), b"caf\xe9 au lait\n")
+    os.write(fake_stream.fileno(), "still working \u2713\n".encode("utf-8"))
+    # A replacement character that was printed on purpose isn't counted.
+    os.write(fake_stream.fileno(), "printed \ufffd\n".encode("utf-8"))
+
+    r.drain()
+
+    assert output == [
+        ("fake_stream", "caf\ufffd au lait\n"),
+        ("fake_stream", "still working \u2713\n"),
+        ("fake_stream", "printed \ufffd\n"),
+        ("fake_stream", "[cog] replaced 1 invalid UTF-8 sequences in fake_stream\n"),
+    ]
+    assert ws.decode_errors == 1
+
+    # Only new replacements are reported at the next drain.
+    os.write(fake_stream.fileno(), b"ok\n")
+    r.drain()
+    assert output[-1] == ("fake_stream", "ok\n")
+
+    r.shutdown()
+
+
 def test_stream_redirector_with_no_streams_raises():
     def _write_hook(stream_name, original_stream, data):
         pass
@@ -1,17 +1,37 @@
 import base64
 import io
+import json
+import platform
+import socket
+import threading
 import time
 import unittest.mock as mock
 
+import cog
+import pytest
+import requests
 import responses
+import uvicorn
+from cog.server.http import (
+    Server,
+    create_app,
+    create_multi_app,
+    make_parser,
+    make_server_config,
+    parse_models,
+)
+from fastapi import FastAPI
+from fastapi.testclient import TestClient
 from PIL import Image
 from responses import matchers
 
 from .conftest import (
+    _fixture_path,
     make_client,
     uses_predictor,
     uses_predictor_with_client_options,
     uses_trainer,
+    wait_for_setup,
 )
 
 
@@ -23,6 +43,51 @@ def test_setup_healthcheck():
     assert data["setup"] == {}
 
 
+def test_liveness_is_healthy_during_setup():
+    client = make_client(fixture_name="slow_setup")
+    resp = client.get("/health-check/live")
+    assert resp.status_code == 200
+    assert resp.json() == {"status": "ALIVE"}
+
+    resp = client.get("/health-check")
+    assert resp.json()["status"] == "STARTING"
+
+
+@uses_predictor("exc_in_setup")
+def test_liveness_is_unhealthy_when_setup_fails(client):
+    resp = client.get("/health-check/live")
+    assert resp.status_code == 503
+    assert resp.json() == {"status": "DEAD"}
+
+
+def test_version_is_available_during_setup():
+    client = make_client(fixture_name="slow_setup")
+    resp = client.get("/version")
+    assert resp.status_code == 200
+    assert resp.headers["cache-control"] == "max-age=300"
+    data = resp.json()
+    assert data["cog"] == cog.__version__
+    assert data["python"] == platform.python_version()
+
+
+@uses_predictor("sleep")
+def test_healthcheck_reports_concurrency(client):
+    resp = client.get("/health-check")
+    assert resp.json()["concurrency"] == {"max": 1, "current": 0}
+
+    resp = client.post(
+        "/predictions",
+        json={"id": "abcd1234", "input": {"sleep": 1}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.get("/health-check")
+    assert resp.json()["concurrency"] == {"max": 1, "current": 1}
+
+    client.post("/predictions/abcd1234/cancel")
+
+
 @uses_predictor("setup")
 def test_setup_is_called(client, match):
     resp = client.post("/predictions")
@@ -115,6 +180,13 @@ def test_openapi_specification(client, static_schema):
                     "schema": {"title": "Prediction ID", "type": "string"},
                 }
             ],
+            "requestBody": {
+                "content": {
+                    "application/json": {
+                        "schema": {"$ref": "#/components/schemas/CancelRequest"}
+                    }
+                }
+            },
             "responses": {
                 "200": {
                     "content": {"application/json": {"schema": mock.ANY}},
@@ -153,12 +225,14 @@ def test_openapi_specification(client, static_schema):
                 "title": "Default Without Input",
                 "type": "string",
                 "default": "default",
+                "example": "default",
                 "x-order": 1,
             },
             "input_with_default": {
                 "title": "Input With Default",
                 "type": "integer",
                 "default": -10,
+                "example": -10,
                 "x-order": 2,
             },
             "path": {
@@ -203,6 +277,34 @@ def test_openapi_specification(client, static_schema):
     }
 
 
+@uses_predictor("openapi_input_enum_default")
+def test_openapi_specification_with_enum_member_defaults(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["quality"]["default"] == "high"
+    assert properties["preset"]["default"] == "LOW"
+    assert schema["components"]["schemas"]["quality"]["enum"] == ["low", "high"]
+
+    resp = client.post("/predictions")
+    assert resp.json()["output"] == "high LOW"
+
+
+@uses_predictor("openapi_input_docstring")
+def test_openapi_specification_with_docstring_descriptions(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["text"]["description"] == "The text to repeat."
+    assert properties["count"]["description"] == "How many times to repeat it"
+
+
 @uses_predictor("openapi_custom_output_type")
 def test_openapi_specification_with_custom_user_defined_output_type(
     client, static_schema
@@ -234,6 +336,38 @@ def test_openapi_specification_with_custom_user_defined_output_type(
     }
 
 
+@uses_predictor("openapi_output_type_forward_ref")
+def test_openapi_specification_with_forward_referenced_output_type(
+    client, static_schema, match
+):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Output"] == {
+        "$ref": "#/components/schemas/MyOutput",
+        "title": "Output",
+    }
+    assert schema["components"]["schemas"]["MyOutput"]["properties"] == {
+        "foo_number": {"title": "Foo Number", "type": "integer", "default": 42},
+        "foo_string": {
+            "title": "Foo String",
+            "type": "string",
+            "default": "meaning of life",
+        },
+    }
+
+    resp = client.post("/predictions")
+    assert resp.status_code == 200
+    assert resp.json() == match(
+        {
+            "status": "succeeded",
+            "output": {"foo_number": 1, "foo_string": "meaning of life"},
+        }
+    )
+
+
 @uses_predictor("openapi_output_type")
 def test_openapi_specification_with_custom_user_defined_output_type_called_output(
     client, static_schema
@@ -292,6 +426,83 @@ def test_openapi_specification_with_yield_with_concatenate_iterator(
     }
 
 
+@uses_predictor("async_yield_concatenate_iterator")
+def test_openapi_specification_with_async_concatenate_iterator(
+    client, static_schema
+):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Output"] == {
+        "title": "Output",
+        "type": "array",
+        "items": {
+            "type": "string",
+        },
+        "x-cog-array-type": "iterator",
+        "x-cog-array-display": "concatenate",
+    }
+
+    resp = client.post("/predictions")
+    assert resp.status_code == 200
+    assert resp.json()["output"] == ["foo", "bar", "baz"]
+
+
+@uses_predictor("openapi_input_when")
+def test_openapi_specification_with_input_conditions(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    steps = schema["components"]["schemas"]["Input"]["properties"]["steps"]
+    assert steps["x-cog-when"] == {"field": "mode", "equals": "advanced"}
+
+    # Conditions aren't enforced.
+    resp = client.post("/predictions", json={"input": {"steps": 5}})
+    assert resp.json()["output"] == "simple 5"
+
+
+@uses_predictor("openapi_input_title_example")
+def test_openapi_specification_with_input_title_and_example(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["prompt"] == {
+        "title": "Prompt text",
+        "type": "string",
+        "example": "an onion",
+        "format": "textarea",
+        "x-order": 0,
+    }
+    assert properties["seed"]["title"] == "Random seed"
+
+
+@uses_predictor("openapi_input_default_example")
+def test_openapi_specification_with_default_as_example(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["scale"] == {
+        "title": "Scale",
+        "type": "number",
+        "default": 1.5,
+        "example": 1.5,
+        "x-order": 0,
+    }
+    # An explicit example takes precedence over the default.
+    assert properties["seed"]["example"] == 42
+    assert "example" not in properties["token"]
+
+
 @uses_predictor("openapi_output_list")
 def test_openapi_specification_with_list(client, static_schema):
     resp = client.get("/openapi.json")
@@ -308,6 +519,57 @@ def test_openapi_specification_with_list(client, static_schema):
     }
 
 
+@uses_predictor("openapi_output_example")
+def test_openapi_specification_with_output_example(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Output"] == {
+        "title": "Output",
+        "type": "array",
+        "items": {
+            "type": "string",
+        },
+        "example": ["hello", "world"],
+    }
+
+
+@uses_predictor("output_nested_models")
+def test_openapi_specification_with_nested_output_models(client):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schemas = resp.json()["components"]["schemas"]
+    assert schemas["Output"]["$ref"] == "#/components/schemas/Box"
+    assert schemas["Box"]["properties"]["top_left"] == {
+        "$ref": "#/components/schemas/Point"
+    }
+    assert schemas["Box"]["properties"]["bottom_right"] == {
+        "$ref": "#/components/schemas/Point"
+    }
+    assert [name for name in schemas if name.endswith("Point")] == ["Point"]
+    assert [name for name in schemas if name.endswith("Box")] == ["Box"]
+
+
+@uses_predictor("input_secret")
+def test_openapi_specification_with_secret(client, static_schema):
+    resp = client.get("/openapi.json")
+    assert resp.status_code == 200
+
+    schema = resp.json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Input"]["properties"]["secret"] == {
+        "title": "Secret",
+        "type": "string",
+        "format": "password",
+        "writeOnly": True,
+        "x-cog-secret": True,
+        "x-order": 0,
+    }
+
+
 @uses_predictor("openapi_input_int_choices")
 def test_openapi_specification_with_int_choices(client, static_schema):
     resp = client.get("/openapi.json")
@@ -370,6 +632,19 @@ def test_train_openapi_specification(client):
     }
 
 
+def test_train_without_train_method_fails_setup():
+    client = make_client(
+        "input_none", additional_config={"train": _fixture_path("input_none")}
+    )
+    resp = client.get("/health-check")
+    data = resp.json()
+    assert data["status"] == "SETUP_FAILED"
+    assert (
+        "train mode requested but no train() found on Predictor"
+        in data["setup"]["logs"]
+    )
+
+
 @uses_predictor("yield_strings")
 def test_yielding_strings_from_generator_predictors(client, match):
     resp = client.post("/predictions")
@@ -379,6 +654,43 @@ def test_yielding_strings_from_generator_predictors(client, match):
     )
 
 
+@uses_predictor("yield_strings")
+def test_streaming_ndjson_from_generator_predictors(client, match):
+    resp = client.post("/predictions", headers={"Accept": "application/x-ndjson"})
+    assert resp.status_code == 200
+    assert resp.headers["content-type"] == "application/x-ndjson"
+    lines = [json.loads(line) for line in resp.text.splitlines()]
+    assert lines[:-1] == [{"output": "foo"}, {"output": "bar"}, {"output": "baz"}]
+    assert lines[-1] == match({"status": "succeeded"})
+    assert "output" not in lines[-1]
+
+
+@uses_predictor("yield_strings")
+def test_streaming_ndjson_ends_with_failure_if_result_is_lost(client):
+    runner = client.app.state.runner
+    predict = runner.predict
+
+    def predict_and_lose_result(*args, **kwargs):
+        response, async_result = predict(*args, **kwargs)
+        async_result.get = mock.Mock(side_effect=RuntimeError("worker crashed"))
+        return response, async_result
+
+    with mock.patch.object(runner, "predict", predict_and_lose_result):
+        resp = client.post(
+            "/predictions", headers={"Accept": "application/x-ndjson"}
+        )
+    assert resp.status_code == 200
+    lines = [json.loads(line) for line in resp.text.splitlines()]
+    assert lines[-1] == {"status": "failed", "error": "worker crashed"}
+
+
+@uses_predictor("input_none")
+def test_ndjson_is_ignored_for_non_iterator_predictors(client, match):
+    resp = client.post("/predictions", headers={"Accept": "application/x-ndjson"})
+    assert resp.headers["content-type"] == "application/json"
+    assert resp.json() == match({"status": "succeeded", "output": "foobar"})
+
+
 @uses_predictor("yield_concatenate_iterator")
 def test_yielding_strings_from_concatenate_iterator(client, match):
     resp = client.post("/predictions")
@@ -388,6 +700,16 @@ def test_yielding_strings_from_concatenate_iterator(client, match):
     )
 
 
+@uses_predictor("record_metric")
+def test_prediction_includes_recorded_metrics(client):
+    resp = client.post("/predictions", json={"input": {"steps": 3}})
+    assert resp.status_code == 200
+    metrics = resp.json()["metrics"]
+    assert metrics["input_token_count"] == 3
+    assert metrics["steps_completed"] == 3
+    assert metrics["predict_time"] >= 0
+
+
 @uses_predictor("yield_strings_file_input")
 def test_yielding_strings_from_generator_predictors_file_input(client, match):
     resp = client.post(
@@ -489,60 +811,264 @@ def test_prediction_idempotent_endpoint_conflict(client, match):
     assert resp2.status_code == 409
 
 
-# a basic end-to-end test for async predictions. if you're adding more
-# exhaustive tests of webhooks, consider adding them to test_runner.py
-@responses.activate
-@uses_predictor("input_string")
-def test_asynchronous_prediction_endpoint(client, match):
-    webhook = responses.post(
-        "https://example.com/webhook",
-        match=[
-            matchers.json_params_matcher(
-                {
-                    "id": "12345abcde",
-                    "status": "succeeded",
-                    "output": "hello world",
-                },
-                strict_match=False,
-            )
-        ],
-        status=200,
+@uses_predictor("counter")
+def test_prediction_idempotent_endpoint_replays_completed_prediction(client, match):
+    resp1 = client.put("/predictions/abcd1234", json={"input": {}})
+    resp2 = client.put("/predictions/abcd1234", json={"input": {}})
+    assert resp1.status_code == 200
+    assert resp1.json() == match({"id": "abcd1234", "status": "succeeded", "output": 1})
+    assert resp2.status_code == 200
+    assert resp2.json() == match({"id": "abcd1234", "status": "succeeded", "output": 1})
+
+    resp3 = client.put("/predictions/5678efgh", json={"input": {}})
+    assert resp3.json() == match({"id": "5678efgh", "status": "succeeded", "output": 2})
+
+
+@uses_predictor_with_client_options(
+    "counter", completed_prediction_ttl=60, max_completed_predictions=1
+)
+def test_completed_predictions_are_bounded(client, match):
+    client.put("/predictions/abcd1234", json={"input": {}})
+    client.put("/predictions/5678efgh", json={"input": {}})
+
+    assert client.get("/predictions/abcd1234").status_code == 404
+    resp = client.put("/predictions/abcd1234", json={"input": {}})
+    assert resp.json() == match({"id": "abcd1234", "status": "succeeded", "output": 3})
+
+
+@uses_predictor_with_client_options("sleep", queue_depth=1)
+def test_prediction_waits_in_queue_for_busy_runner(client, match):
+    resp1 = client.put(
+        "/predictions/abcd1234",
+        json={"input": {"sleep": 0.5}},
+        headers={"Prefer": "respond-async"},
     )
+    assert resp1.status_code == 202
 
-    resp = client.post(
-        "/predictions",
-        json={
-            "id": "12345abcde",
-            "input": {"text": "hello world"},
-            "webhook": "https://example.com/webhook",
-            "webhook_events_filter": ["completed"],
-        },
+    resp2 = client.post("/predictions", json={"input": {"sleep": 0}})
+    assert resp2.status_code == 200
+    assert resp2.json() == match(
+        {"status": "succeeded", "output": "done in 0.0 seconds"}
+    )
+
+
+@uses_predictor_with_client_options("sleep", queue_depth=2, queue_timeout=5)
+def test_queued_predictions_run_in_order(client, match):
+    resp = client.put(
+        "/predictions/first",
+        json={"input": {"sleep": 0.5}},
         headers={"Prefer": "respond-async"},
     )
     assert resp.status_code == 202
 
+    results = {}
+
+    def queue_prediction(prediction_id):
+        results[prediction_id] = client.put(
+            f"/predictions/{prediction_id}", json={"input": {"sleep": 0.2}}
+        )
+
+    threads = []
+    for prediction_id in ("second", "third"):
+        thread = threading.Thread(target=queue_prediction, args=(prediction_id,))
+        thread.start()
+        threads.append(thread)
+        time.sleep(0.1)
+    for thread in threads:
+        thread.join()
+
+    # Both waited for their turn, rather than the one that lost the race for
+    # the slot giving up.
+    second = results["second"].json()
+    third = results["third"].json()
+    assert second == match({"status": "succeeded"})
+    assert third == match({"status": "succeeded"})
+    assert second["started_at"] < third["started_at"]
+
+
+@uses_predictor_with_client_options("sleep", queue_depth=1, queue_timeout=0.1)
+def test_queued_prediction_gives_up_after_timeout(client, match):
+    resp1 = client.put(
+        "/predictions/abcd1234",
+        json={"input": {"sleep": 1}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp1.status_code == 202
+
+    resp2 = client.post("/predictions", json={"input": {"sleep": 0}})
+    assert resp2.status_code == 409
+
+
+@uses_predictor("response_header")
+def test_predictor_can_set_response_headers(client, match):
+    resp = client.post("/predictions", json={"input": {}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded", "output": "hello"})
+    assert resp.headers["Cache-Control"] == "max-age=60"
+
+
+@uses_predictor("response_header")
+def test_predictor_cannot_set_other_response_headers(client, match):
+    resp = client.post("/predictions", json={"input": {"header": "Set-Cookie"}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "failed"})
+    assert "Set-Cookie" in resp.json()["error"]
+    assert "set-cookie" not in resp.headers
+
+
+@uses_predictor("prediction_error")
+def test_failed_prediction_returns_200_by_default(client, match):
+    resp = client.post("/predictions")
+    assert resp.status_code == 200
     assert resp.json() == match(
-        {"status": "processing", "output": None, "started_at": mock.ANY}
+        {"status": "failed", "error": "something went wrong", "output": None}
     )
-    assert resp.json()["started_at"] is not None
 
-    n = 0
-    while webhook.call_count < 1 and n < 10:
-        time.sleep(0.1)
-        n += 1
+
+@uses_predictor("prediction_error")
+def test_failed_prediction_includes_traceback(client):
+    resp = client.post("/predictions")
+    data = resp.json()
+    assert data["error"] == "something went wrong"
+    assert data["traceback"].startswith("Traceback (most recent call last):")
+    assert 'raise ValueError("something went wrong")' in data["traceback"]
+    assert data["traceback"].endswith("ValueError: something went wrong\n")
 
 
-# End-to-end test for passing tracing headers on to downstream services.
-@responses.activate
 @uses_predictor_with_client_options(
-    "output_file", upload_url="https://example.com/upload"
+    "prediction_error", failed_prediction_http_status=500
 )
-def test_asynchronous_prediction_endpoint_with_trace_context(client, match):
-    webhook = responses.post(
-        "https://example.com/webhook",
-        match=[
-            matchers.json_params_matcher(
-                {
+def test_failed_prediction_http_status(client, match):
+    resp = client.post("/predictions")
+    assert resp.status_code == 500
+    assert resp.json() == match(
+        {"status": "failed", "error": "something went wrong", "output": None}
+    )
+
+
+@uses_predictor_with_client_options("input_none", failed_prediction_http_status=500)
+def test_failed_prediction_http_status_does_not_affect_success(client, match):
+    resp = client.post("/predictions")
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded", "output": "foobar"})
+
+
+@uses_predictor_with_client_options(
+    "prediction_error", failed_prediction_http_status=500
+)
+def test_retried_failed_prediction_gets_the_same_status(client, match):
+    resp = client.put("/predictions/abcd1234", json={"input": {}})
+    assert resp.status_code == 500
+
+    resp = client.put("/predictions/abcd1234", json={"input": {}})
+    assert resp.status_code == 500
+    assert resp.json() == match(
+        {"id": "abcd1234", "status": "failed", "error": "something went wrong"}
+    )
+
+
+@uses_predictor("response_header")
+def test_retried_prediction_gets_the_same_response_headers(client):
+    resp = client.put("/predictions/abcd1234", json={"input": {}})
+    assert resp.headers["Cache-Control"] == "max-age=60"
+
+    resp = client.put("/predictions/abcd1234", json={"input": {}})
+    assert resp.json()["status"] == "succeeded"
+    assert resp.headers["Cache-Control"] == "max-age=60"
+
+
+def test_failed_prediction_http_status_does_not_change_body():
+    bodies = []
+    for status_code in (200, 500):
+        with make_client(
+            "prediction_error", failed_prediction_http_status=status_code
+        ) as client:
+            wait_for_setup(client)
+            resp = client.post("/predictions")
+            assert resp.status_code == status_code
+            body = resp.json()
+            for key in ("started_at", "completed_at", "metrics"):
+                del body[key]
+            bodies.append(body)
+
+    assert bodies[0] == bodies[1]
+
+
+@uses_predictor("sleep")
+def test_prediction_conflict_includes_retry_after(client):
+    resp1 = client.post(
+        "/predictions",
+        json={"input": {"sleep": 1}},
+        headers={"Prefer": "respond-async"},
+    )
+    resp2 = client.post("/predictions", json={"input": {"sleep": 1}})
+    assert resp1.status_code == 202
+    assert resp2.status_code == 409
+    assert resp2.headers["Retry-After"] == "1"
+
+
+def test_prediction_during_setup_returns_503_with_retry_after():
+    with make_client(fixture_name="slow_setup", retry_after=5) as client:
+        resp = client.post("/predictions")
+        assert resp.status_code == 503
+        assert resp.headers["Retry-After"] == "5"
+        assert resp.json() == {"detail": "Setup has not finished yet"}
+
+
+# a basic end-to-end test for async predictions. if you're adding more
+# exhaustive tests of webhooks, consider adding them to test_runner.py
+@responses.activate
+@uses_predictor("input_string")
+def test_asynchronous_prediction_endpoint(client, match):
+    webhook = responses.post(
+        "https://example.com/webhook",
+        match=[
+            matchers.json_params_matcher(
+                {
+                    "id": "12345abcde",
+                    "status": "succeeded",
+                    "output": "hello world",
+                },
+                strict_match=False,
+            )
+        ],
+        status=200,
+    )
+
+    resp = client.post(
+        "/predictions",
+        json={
+            "id": "12345abcde",
+            "input": {"text": "hello world"},
+            "webhook": "https://example.com/webhook",
+            "webhook_events_filter": ["completed"],
+        },
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    assert resp.json() == match(
+        {"status": "processing", "output": None, "started_at": mock.ANY}
+    )
+    assert resp.json()["started_at"] is not None
+
+    n = 0
+    while webhook.call_count < 1 and n < 10:
+        time.sleep(0.1)
+        n += 1
+
+
+# End-to-end test for passing tracing headers on to downstream services.
+@responses.activate
+@uses_predictor_with_client_options(
+    "output_file", upload_url="https://example.com/upload"
+)
+def test_asynchronous_prediction_endpoint_with_trace_context(client, match):
+    webhook = responses.post(
+        "https://example.com/webhook",
+        match=[
+            matchers.json_params_matcher(
+                {
                     "id": "12345abcde",
                     "status": "succeeded",
                     "output": "https://example.com/upload/file",
@@ -603,6 +1129,83 @@ def test_asynchronous_prediction_endpoint_with_trace_context(client, match):
     assert webhook.call_count == 1
 
 
+@responses.activate
+@uses_predictor("yield_strings")
+def test_asynchronous_prediction_starting_webhook(client):
+    responses.post("https://example.com/webhook", status=200)
+
+    resp = client.post(
+        "/predictions",
+        json={
+            "id": "abcd1234",
+            "webhook": "https://example.com/webhook",
+            "webhook_events_filter": ["starting", "output", "completed"],
+        },
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+    assert client.app.state.runner.wait_for_idle(timeout=5)
+
+    statuses = [json.loads(c.request.body)["status"] for c in responses.calls]
+    assert statuses[0] == "starting"
+    assert statuses[1] == "processing"
+    assert statuses[-1] == "succeeded"
+
+
+@responses.activate
+@uses_predictor("yield_strings")
+def test_asynchronous_prediction_no_starting_webhook_by_default(client):
+    responses.post("https://example.com/webhook", status=200)
+
+    resp = client.post(
+        "/predictions",
+        json={"id": "abcd1234", "webhook": "https://example.com/webhook"},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+    assert client.app.state.runner.wait_for_idle(timeout=5)
+
+    statuses = [json.loads(c.request.body)["status"] for c in responses.calls]
+    assert "starting" not in statuses
+    assert statuses[-1] == "succeeded"
+
+
+@responses.activate
+@uses_predictor("sleep")
+def test_asynchronous_prediction_cancel_sends_canceled_webhook(client):
+    webhook = responses.post(
+        "https://example.com/webhook",
+        match=[
+            matchers.json_params_matcher(
+                {"id": "abcd1234", "status": "canceled"}, strict_match=False
+            )
+        ],
+        status=200,
+    )
+
+    resp = client.post(
+        "/predictions",
+        json={
+            "id": "abcd1234",
+            "input": {"sleep": 60},
+            "webhook": "https://example.com/webhook",
+            "webhook_events_filter": ["canceled"],
+        },
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/predictions/abcd1234/cancel")
+    assert resp.status_code == 200
+
+    n = 0
+    while webhook.call_count < 1 and n < 10:
+        time.sleep(0.1)
+        n += 1
+
+    assert webhook.call_count == 1
+
+
 @uses_predictor("sleep")
 def test_prediction_cancel(client):
     resp = client.post("/predictions/123/cancel")
@@ -622,6 +1225,528 @@ def test_prediction_cancel(client):
     assert resp.status_code == 200
 
 
+@uses_predictor("yield_slowly")
+def test_sync_prediction_is_canceled_when_client_disconnects(client, match):
+    checks = 0
+
+    # The client goes away after a few outputs have been produced.
+    async def is_disconnected(self):
+        nonlocal checks
+        checks += 1
+        return checks > 3
+
+    with mock.patch("starlette.requests.Request.is_disconnected", is_disconnected):
+        resp = client.post("/predictions", json={"input": {"count": 1000}})
+
+    assert resp.status_code == 200
+    assert resp.json() == match(
+        {"status": "canceled", "cancel_reason": "client disconnected"}
+    )
+    assert 0 < len(resp.json()["output"]) < 1000
+
+    resp = client.get("/health-check")
+    assert resp.json()["status"] == "READY"
+
+
+@uses_predictor("sleep")
+def test_running_prediction_completes_after_shutdown(client, match):
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 0.5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/shutdown")
+    assert resp.status_code == 200
+
+    resp = client.post("/predictions", json={"input": {"sleep": 0}})
+    assert resp.status_code == 503
+    assert resp.json() == {"detail": "Server is shutting down"}
+    resp = client.put("/predictions/456", json={"input": {"sleep": 0}})
+    assert resp.status_code == 503
+
+    assert client.app.state.runner.wait_for_idle(timeout=5)
+    resp = client.get("/predictions/123")
+    assert resp.json() == match({"id": "123", "status": "succeeded"})
+
+
+@uses_predictor_with_client_options("sleep", queue_depth=1, queue_timeout=5)
+def test_queued_prediction_is_refused_after_shutdown(client):
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 0.5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    results = {}
+
+    def queue_prediction():
+        results["queued"] = client.put("/predictions/456", json={"input": {}})
+
+    thread = threading.Thread(target=queue_prediction)
+    thread.start()
+    time.sleep(0.1)
+    resp = client.post("/shutdown")
+    assert resp.status_code == 200
+    thread.join()
+
+    # The queued request got the slot once the running prediction finished,
+    # but shutdown had started by then, so it wasn't run.
+    assert results["queued"].status_code == 503
+    assert results["queued"].json() == {"detail": "Server is shutting down"}
+    assert client.get("/predictions/456").status_code == 404
+
+
+@uses_predictor("sleep")
+def test_prediction_cancel_with_reason(client, match):
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 1}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/predictions/123/cancel", json={"reason": "user request"})
+    assert resp.status_code == 200
+
+    resp = client.get("/predictions/123")
+    n = 0
+    while resp.json()["status"] == "processing" and n < 20:
+        time.sleep(0.1)
+        resp = client.get("/predictions/123")
+        n += 1
+    assert resp.json() == match(
+        {"id": "123", "status": "canceled", "cancel_reason": "user request"}
+    )
+
+
+@uses_predictor("async_sleep")
+def test_async_predictor_prediction_cancel(client, match):
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 60}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/predictions/123/cancel")
+    assert resp.status_code == 200
+
+    resp = client.get("/predictions/123")
+    n = 0
+    while resp.json()["status"] == "processing" and n < 20:
+        time.sleep(0.1)
+        resp = client.get("/predictions/123")
+        n += 1
+    assert resp.json() == match({"id": "123", "status": "canceled"})
+
+    # The worker is free to run another prediction.
+    resp = client.post("/predictions", json={"input": {"sleep": 0}})
+    assert resp.json() == match(
+        {"status": "succeeded", "output": "done in 0.0 seconds"}
+    )
+
+
+@uses_predictor("sleep")
+def test_get_prediction(client, match):
+    resp = client.get("/predictions/123")
+    assert resp.status_code == 404
+
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 0.5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.get("/predictions/123")
+    assert resp.status_code == 200
+    assert resp.json() == match({"id": "123", "status": "processing"})
+    assert resp.headers["Retry-After"] == "1"
+
+    resp = client.get("/predictions/456")
+    assert resp.status_code == 404
+
+    n = 0
+    while resp.json()["status"] == "processing" and n < 20:
+        time.sleep(0.1)
+        resp = client.get("/predictions/123")
+        n += 1
+
+    assert resp.status_code == 200
+    assert resp.json() == match(
+        {"id": "123", "status": "succeeded", "output": "done in 0.5 seconds"}
+    )
+    assert "Retry-After" not in resp.headers
+
+
+@uses_predictor_with_client_options("sleep", admin_token="secret")
+def test_admin_cancel_slot(client, match):
+    headers = {"Authorization": "Bearer secret"}
+
+    resp = client.post("/admin/slots/0/cancel", headers=headers)
+    assert resp.status_code == 404
+
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/admin/slots/0/cancel")
+    assert resp.status_code == 401
+    resp = client.post(
+        "/admin/slots/0/cancel", headers={"Authorization": "Bearer wrong"}
+    )
+    assert resp.status_code == 401
+
+    resp = client.post("/admin/slots/1/cancel", headers=headers)
+    assert resp.status_code == 404
+
+    resp = client.post("/admin/slots/0/cancel", headers=headers)
+    assert resp.status_code == 200
+    assert resp.json() == {"id": "123"}
+
+    resp = client.get("/predictions/123")
+    n = 0
+    while resp.json()["status"] == "processing" and n < 20:
+        time.sleep(0.1)
+        resp = client.get("/predictions/123")
+        n += 1
+    assert resp.json() == match({"id": "123", "status": "canceled"})
+
+
+@uses_predictor_with_client_options("sleep", admin_token="secret")
+def test_admin_cancel_all(client, match):
+    headers = {"Authorization": "Bearer secret"}
+
+    resp = client.post("/admin/cancel-all")
+    assert resp.status_code == 401
+
+    resp = client.post("/admin/cancel-all", headers=headers)
+    assert resp.status_code == 200
+    assert resp.json() == {"canceled": 0}
+
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/admin/cancel-all", headers=headers)
+    assert resp.status_code == 200
+    assert resp.json() == {"canceled": 1}
+
+    resp = client.get("/predictions/123")
+    n = 0
+    while resp.json()["status"] == "processing" and n < 20:
+        time.sleep(0.1)
+        resp = client.get("/predictions/123")
+        n += 1
+    assert resp.json() == match({"id": "123", "status": "canceled"})
+
+    resp = client.post("/admin/cancel-all", headers=headers)
+    assert resp.status_code == 200
+    assert resp.json() == {"canceled": 0}
+
+
+@uses_predictor_with_client_options(
+    "sleep", admin_token="secret", recent_predictions=2
+)
+def test_admin_list_predictions(client, match):
+    headers = {"Authorization": "Bearer secret"}
+
+    resp = client.get("/admin/predictions")
+    assert resp.status_code == 401
+
+    resp = client.get("/admin/predictions", headers=headers)
+    assert resp.status_code == 200
+    assert resp.json() == {"predictions": []}
+
+    resp = client.put("/predictions/a", json={"input": {"sleep": 0}})
+    assert resp.json() == match({"status": "succeeded"})
+
+    resp = client.put(
+        "/predictions/b",
+        json={"input": {"sleep": 5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+    client.post("/predictions/b/cancel")
+    assert client.app.state.runner.wait_for_idle(timeout=5)
+
+    resp = client.put("/predictions/c", json={"input": {"sleep": 0}})
+    assert resp.json() == match({"status": "succeeded"})
+
+    # Only the most recent predictions are kept, and they're newest first.
+    resp = client.get("/admin/predictions", headers=headers)
+    assert resp.status_code == 200
+    predictions = resp.json()["predictions"]
+    assert predictions == [
+        match({"id": "c", "status": "succeeded", "error": None}),
+        match({"id": "b", "status": "canceled", "error": None}),
+    ]
+    assert predictions[0]["predict_time"] >= 0
+
+
+@uses_predictor_with_client_options("sleep", admin_token="secret")
+def test_admin_reload(client, match):
+    headers = {"Authorization": "Bearer secret"}
+    pid = client.app.state.runner._worker.pid()
+
+    resp = client.post("/admin/reload")
+    assert resp.status_code == 401
+
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"sleep": 0.5}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.post("/admin/reload", headers=headers)
+    assert resp.status_code == 202
+    assert client.get("/health-check").json()["status"] == "STARTING"
+
+    # The running prediction finishes before the worker is replaced.
+    wait_for_setup(client)
+    assert client.get("/health-check").json()["status"] == "READY"
+    assert client.get("/predictions/123").json() == match(
+        {"id": "123", "status": "succeeded"}
+    )
+    assert client.app.state.runner._worker.pid() != pid
+
+    resp = client.post("/predictions", json={"input": {"sleep": 0}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded"})
+
+
+@uses_predictor_with_client_options("sleep", admin_token="secret")
+def test_predictions_are_refused_while_reload_is_pending(client, match):
+    runner = client.app.state.runner
+    wait_for_idle = runner.wait_for_idle
+    release = threading.Event()
+
+    def delayed_wait_for_idle(timeout):
+        # Holds the reload back, as if a prediction were still running.
+        release.wait()
+        return wait_for_idle(timeout)
+
+    with mock.patch.object(runner, "wait_for_idle", delayed_wait_for_idle):
+        resp = client.post(
+            "/admin/reload", headers={"Authorization": "Bearer secret"}
+        )
+        assert resp.status_code == 202
+
+        resp = client.post("/predictions", json={"input": {"sleep": 0}})
+        assert resp.status_code == 503
+        assert resp.json() == {"detail": "Setup has not finished yet"}
+        resp = client.put("/predictions/123", json={"input": {"sleep": 0}})
+        assert resp.status_code == 503
+
+        release.set()
+        wait_for_setup(client)
+
+    resp = client.post("/predictions", json={"input": {"sleep": 0}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded"})
+
+
+@uses_predictor("sleep")
+def test_admin_routes_disabled_without_token(client):
+    resp = client.post("/admin/slots/0/cancel")
+    assert resp.status_code == 404
+    resp = client.post("/admin/reload")
+    assert resp.status_code == 404
+
+
+@uses_predictor_with_client_options("input_string", max_body_size=100)
+def test_request_body_too_large(client, match):
+    resp = client.post("/predictions", json={"input": {"text": "a" * 200}})
+    assert resp.status_code == 413
+    assert resp.json() == {
+        "detail": "Request body is larger than the limit of 100 bytes",
+    }
+
+    resp = client.post("/predictions", json={"input": {"text": "baz"}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded", "output": "baz"})
+
+    resp = client.get("/health-check")
+    assert resp.status_code == 200
+
+
+def test_setup_logs_are_streamed():
+    client = make_client("setup_logs")
+    with client:
+        resp = client.get("/setup-logs")
+        assert resp.status_code == 200
+        assert resp.text == "downloading weights\nloading model\nready\n"
+
+        resp = client.get("/health-check")
+        assert resp.json()["status"] == "READY"
+        assert resp.json()["setup"]["logs"] == (
+            "downloading weights\nloading model\nready\n"
+        )
+
+
+def test_setup_logs_are_streamed_while_setup_runs():
+    # The test client only returns a response once it's complete, so this
+    # needs a real server to read the logs as they arrive.
+    with socket.socket() as sock:
+        sock.bind(("127.0.0.1", 0))
+        port = sock.getsockname()[1]
+    app = create_app(
+        config={"predict": _fixture_path("setup_logs")},
+        shutdown_event=threading.Event(),
+    )
+    server = Server(
+        config=uvicorn.Config(app, host="127.0.0.1", port=port, log_config=None)
+    )
+    server.start()
+    try:
+        while not server.started:
+            time.sleep(0.01)
+
+        url = f"http://127.0.0.1:{port}/setup-logs"
+        with requests.get(url, stream=True, timeout=30) as resp:
+            assert resp.status_code == 200
+            chunks = resp.iter_content(chunk_size=None, decode_unicode=True)
+            first = next(chunks)
+            assert first == "downloading weights\n"
+            # Setup is still running, as it sleeps between the lines it logs.
+            assert app.state.setup_result is None
+
+            rest = "".join(chunks)
+            assert first + rest == "downloading weights\nloading model\nready\n"
+    finally:
+        server.stop()
+
+
+def test_streamed_predictions_are_not_compressed():
+    # Compressing the stream would hold back its output until there was enough
+    # of it, so this needs a real server to see each line as it arrives.
+    with socket.socket() as sock:
+        sock.bind(("127.0.0.1", 0))
+        port = sock.getsockname()[1]
+    app = create_app(
+        config={"predict": _fixture_path("async_yield_then_sleep")},
+        shutdown_event=threading.Event(),
+        gzip_min_size=0,
+    )
+    server = Server(
+        config=uvicorn.Config(app, host="127.0.0.1", port=port, log_config=None)
+    )
+    server.start()
+    try:
+        while not server.started:
+            time.sleep(0.01)
+        url = f"http://127.0.0.1:{port}"
+        while requests.get(f"{url}/health-check").json()["status"] != "READY":
+            time.sleep(0.01)
+
+        with requests.post(
+            f"{url}/predictions",
+            json={"input": {"sleep": 2}},
+            headers={"Accept": "application/x-ndjson", "Accept-Encoding": "gzip"},
+            stream=True,
+            timeout=30,
+        ) as resp:
+            assert resp.status_code == 200
+            assert "content-encoding" not in resp.headers
+            lines = resp.iter_lines(chunk_size=None)
+            assert json.loads(next(lines)) == {"output": "first"}
+            # The prediction is still running, as it sleeps after the first
+            # output.
+            assert app.state.runner.current_prediction() is not None
+
+            rest = [json.loads(line) for line in lines]
+            assert rest[0] == {"output": "second"}
+            assert rest[-1]["status"] == "succeeded"
+    finally:
+        server.stop()
+
+
+def test_health_is_starting_until_gpu_is_available():
+    gpu = threading.Event()
+    with mock.patch("cog.server.runner.gpu_available", gpu.is_set), mock.patch(
+        "cog.server.runner.GPU_POLL_INTERVAL", 0.01
+    ):
+        client = make_client("sleep", gpu_wait_timeout=10)
+        with client:
+            time.sleep(0.2)
+            resp = client.get("/health-check")
+            assert resp.json()["status"] == "STARTING"
+
+            gpu.set()
+            wait_for_setup(client)
+            resp = client.get("/health-check")
+            assert resp.json()["status"] == "READY"
+
+
+@uses_predictor("input_string")
+def test_metrics(client):
+    resp = client.get("/metrics")
+    assert resp.status_code == 200
+    assert resp.headers["content-type"].startswith("text/plain; version=0.0.4")
+    lines = resp.text.splitlines()
+    assert "# TYPE cog_predictions_completed_total counter" in lines
+    assert "# TYPE cog_predict_time_seconds histogram" in lines
+    assert 'cog_predictions_completed_total{status="succeeded"} 0' in lines
+    assert "cog_busy_slots 0" in lines
+    assert any(line.startswith("cog_setup_duration_seconds ") for line in lines)
+
+    resp = client.post("/predictions", json={"input": {"text": "baz"}})
+    assert resp.status_code == 200
+
+    lines = client.get("/metrics").text.splitlines()
+    assert "cog_predictions_started_total 1" in lines
+    assert 'cog_predictions_completed_total{status="succeeded"} 1' in lines
+    assert 'cog_predictions_completed_total{status="failed"} 0' in lines
+    assert 'cog_predict_time_seconds_bucket{le="+Inf"} 1' in lines
+    assert "cog_predict_time_seconds_count 1" in lines
+
+
+@uses_predictor_with_client_options("input_string", enable_metrics=False)
+def test_metrics_disabled(client):
+    resp = client.get("/metrics")
+    assert resp.status_code == 404
+
+
+@uses_predictor_with_client_options("crash_in_predict", max_worker_restarts=1)
+def test_worker_is_restarted_after_crash(client, match):
+    resp = client.post(
+        "/predictions",
+        json={"id": "123", "input": {"crash": True}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+
+    resp = client.get("/predictions/123")
+    n = 0
+    while resp.json()["status"] == "processing" and n < 50:
+        time.sleep(0.1)
+        resp = client.get("/predictions/123")
+        n += 1
+    assert resp.json() == match({"id": "123", "status": "failed"})
+
+    # The worker is restarted in the background, so health goes from BUSY to
+    # STARTING and back to READY.
+    n = 0
+    while client.get("/health-check").json()["status"] != "READY" and n < 100:
+        time.sleep(0.1)
+        n += 1
+    assert client.get("/health-check").json()["status"] == "READY"
+
+    resp = client.post("/predictions", json={"input": {}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded", "output": "ok"})
+
+
 @uses_predictor_with_client_options(
     "setup_weights",
     env={"COG_WEIGHTS": "data:text/plain; charset=utf-8;base64,aGVsbG8="},
@@ -630,3 +1755,77 @@ def test_weights_are_read_from_environment_variables(client, match):
     resp = client.post("/predictions")
     assert resp.status_code == 200
     assert resp.json() == match({"status": "succeeded", "output": "hello"})
+
+
+def test_multiple_models_are_routed_by_prefix(match):
+    shutdown_event = threading.Event()
+    app = create_multi_app(
+        {
+            "strings": create_app(
+                config={"predict": _fixture_path("input_string")},
+                shutdown_event=shutdown_event,
+            ),
+            "integers": create_app(
+                config={"predict": _fixture_path("input_integer")},
+                shutdown_event=shutdown_event,
+            ),
+        }
+    )
+
+    with TestClient(app) as client:
+        for name in ("strings", "integers"):
+            while True:
+                resp = client.get(f"/models/{name}/health-check")
+                if resp.json()["status"] != "STARTING":
+                    break
+                time.sleep(0.01)
+            assert resp.json()["status"] == "READY"
+
+        resp = client.post(
+            "/models/strings/predictions", json={"input": {"text": "hello"}}
+        )
+        assert resp.status_code == 200
+        assert resp.json() == match({"status": "succeeded", "output": "hello"})
+
+        resp = client.post("/models/integers/predictions", json={"input": {"num": 3}})
+        assert resp.status_code == 200
+        assert resp.json() == match({"status": "succeeded", "output": 27})
+
+        schema = client.get("/models/integers/openapi.json").json()
+        assert "num" in schema["components"]["schemas"]["Input"]["properties"]
+
+        assert client.get("/").json() == {
+            "models": ["/models/strings", "/models/integers"]
+        }
+
+
+def test_keep_alive_timeout_is_passed_to_uvicorn():
+    app = FastAPI()
+
+    args = make_parser().parse_args([])
+    assert make_server_config(app, args, port=5000).timeout_keep_alive == 5
+
+    args = make_parser().parse_args(["--keep-alive-timeout", "75"])
+    assert make_server_config(app, args, port=5000).timeout_keep_alive == 75
+
+
+def test_parse_models():
+    parser = make_parser()
+    models = parse_models(parser, ["a=a.py:Predictor", "b=b.py:Predictor"])
+    assert models == {"a": "a.py:Predictor", "b": "b.py:Predictor"}
+
+
+@pytest.mark.parametrize(
+    "models",
+    [
+        ["a"],
+        ["a="],
+        ["=a.py:Predictor"],
+        ["a/b=a.py:Predictor"],
+        ["a=a.py:Predictor", "a=b.py:Predictor"],
+    ],
+)
+def test_parse_models_rejects_bad_values(models, capsys):
+    with pytest.raises(SystemExit):
+        parse_models(make_parser(), models)
+    assert "argument --model" in capsys.readouterr().err
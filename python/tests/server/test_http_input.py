@@ -1,14 +1,21 @@
 import base64
+import io
+import json
 import os
 import threading
+import time
+from unittest import mock
 
+import pytest
 import responses
 from cog import schema
 from cog.server.http import Health, create_app
+from cog.server.runner import RunnerBusyError
+from werkzeug import Response
 
 from tests.server.conftest import _fixture_path
 
-from .conftest import uses_predictor
+from .conftest import uses_predictor, uses_predictor_with_client_options
 
 
 @uses_predictor("input_none")
@@ -34,6 +41,32 @@ def test_empty_input(client, match):
     assert resp.json() == match({"status": "succeeded", "output": "foobar"})
 
 
+@uses_predictor("input_optional")
+def test_missing_required_input(client):
+    resp = client.post("/predictions", json={"input": {"suffix": "!"}})
+    assert resp.status_code == 422
+    assert resp.json() == {
+        "detail": [
+            {
+                "loc": ["body", "input", "text"],
+                "msg": "field required",
+                "type": "value_error.missing",
+            }
+        ]
+    }
+
+
+@uses_predictor("input_optional")
+def test_inputs_with_a_default_of_none_are_optional(client, match):
+    resp = client.post("/predictions", json={"input": {"text": "hello"}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "hello", "status": "succeeded"})
+
+    resp = client.post("/predictions", json={"input": {"text": "hello", "suffix": "!"}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "hello!", "status": "succeeded"})
+
+
 @uses_predictor("input_integer")
 def test_good_int_input(client, match):
     resp = client.post("/predictions", json={"input": {"num": 3}})
@@ -59,6 +92,183 @@ def test_bad_int_input(client):
     assert resp.status_code == 422
 
 
+@uses_predictor("input_numeric_strings")
+def test_numeric_string_inputs_are_coerced(client, match):
+    resp = client.post(
+        "/predictions",
+        json={"input": {"num": "5", "ratio": "0.5", "flag": "true"}},
+    )
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "5 0.5 True", "status": "succeeded"})
+
+
+@pytest.mark.parametrize(
+    "input,type",
+    [
+        ({"num": "abc", "ratio": 0.5, "flag": True}, "type_error.integer"),
+        ({"num": "5.5", "ratio": 0.5, "flag": True}, "type_error.integer"),
+        ({"num": 5, "ratio": "abc", "flag": True}, "type_error.float"),
+        ({"num": 5, "ratio": 0.5, "flag": "maybe"}, "type_error.bool"),
+    ],
+)
+@uses_predictor("input_numeric_strings")
+def test_numeric_string_inputs_that_cannot_be_coerced(client, input, type):
+    resp = client.post("/predictions", json={"input": input})
+    assert resp.status_code == 422
+    [error] = resp.json()["detail"]
+    assert error["type"] == type
+
+
+@uses_predictor("input_set")
+def test_set_input(client, match, static_schema):
+    schema = client.get("/openapi.json").json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Input"]["properties"]["tags"] == {
+        "title": "Tags",
+        "type": "array",
+        "items": {"type": "string"},
+        "uniqueItems": True,
+        "x-order": 0,
+    }
+
+    resp = client.post("/predictions", json={"input": {"tags": ["b", "a"]}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "set: a,b", "status": "succeeded"})
+
+
+@uses_predictor("input_set")
+def test_set_input_rejects_duplicates(client):
+    resp = client.post("/predictions", json={"input": {"tags": ["a", "b", "a"]}})
+    assert resp.status_code == 422
+    assert resp.json()["detail"][0]["loc"] == ["body", "input", "tags"]
+    assert resp.json()["detail"][0]["msg"] == "items must be unique"
+
+
+@uses_predictor("input_annotated")
+def test_annotated_input(client, match, static_schema):
+    schema = client.get("/openapi.json").json()
+    assert schema == static_schema
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["prompt"]["description"] == "A prompt"
+    assert properties["steps"]["default"] == 20
+    assert properties["steps"]["minimum"] == 1
+    assert schema["components"]["schemas"]["Input"]["required"] == ["prompt"]
+
+    resp = client.post("/predictions", json={"input": {"prompt": "hi"}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"status": "succeeded", "output": "hi 20"})
+
+    resp = client.post("/predictions", json={"input": {"prompt": "hi", "steps": 0}})
+    assert resp.status_code == 422
+
+
+@uses_predictor("input_deprecated")
+def test_deprecated_input(client, match, static_schema):
+    schema = client.get("/openapi.json").json()
+    assert schema == static_schema
+    style = schema["components"]["schemas"]["Input"]["properties"]["style"]
+    assert style["deprecated"] is True
+
+    resp = client.post("/predictions", json={"input": {"style": "bold"}})
+    assert resp.status_code == 200
+    assert resp.json() == match(
+        {
+            "status": "succeeded",
+            "output": "hello (bold)",
+            "warnings": ["input 'style' is deprecated"],
+        }
+    )
+
+    # Only using a deprecated input gets a warning, not its default.
+    resp = client.post("/predictions", json={"input": {"text": "hi"}})
+    assert resp.json() == match({"status": "succeeded", "output": "hi (plain)"})
+    assert resp.json()["warnings"] is None
+
+
+@uses_predictor("input_deprecated")
+def test_deprecated_input_warning_in_async_prediction(client, match):
+    resp = client.post(
+        "/predictions",
+        json={"id": "abcd1234", "input": {"style": "bold"}},
+        headers={"Prefer": "respond-async"},
+    )
+    assert resp.status_code == 202
+    assert resp.json()["warnings"] == ["input 'style' is deprecated"]
+
+    assert client.app.state.runner.wait_for_idle(timeout=5)
+    resp = client.get("/predictions/abcd1234")
+    assert resp.json() == match(
+        {"status": "succeeded", "warnings": ["input 'style' is deprecated"]}
+    )
+
+
+@uses_predictor("input_bytes")
+def test_bytes_input(client, match, static_schema):
+    schema = client.get("/openapi.json").json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Input"]["properties"]["data"] == {
+        "title": "Data",
+        "type": "string",
+        "format": "byte",
+        "x-order": 0,
+    }
+
+    data = base64.b64encode(b"\x00\xffcog").decode()
+    resp = client.post("/predictions", json={"input": {"data": data}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "00ff636f67", "status": "succeeded"})
+
+
+@pytest.mark.parametrize("data", ["not base64!", "abc", 123])
+@uses_predictor("input_bytes")
+def test_bad_bytes_input(client, data):
+    resp = client.post("/predictions", json={"input": {"data": data}})
+    assert resp.status_code == 422
+    [error] = resp.json()["detail"]
+    assert error["loc"] == ["body", "input", "data"]
+
+
+@uses_predictor("input_integer")
+def test_batch_predictions(client, match):
+    resp = client.post(
+        "/predictions/batch",
+        json={"inputs": [{"num": 1}, {"num": "foo"}, {"num": 3}]},
+    )
+    assert resp.status_code == 200
+    first, second, third = resp.json()["results"]
+    assert first == match({"status": "succeeded", "output": 1, "input": {"num": 1}})
+    assert second["status"] == "failed"
+    assert "value is not a valid integer" in second["error"]
+    assert third == match({"status": "succeeded", "output": 27, "input": {"num": 3}})
+
+
+@uses_predictor("input_integer")
+def test_batch_predictions_stop_when_runner_is_busy(client, match):
+    runner = client.app.state.runner
+    predict = runner.predict
+    calls = []
+
+    def predict_once(*args, **kwargs):
+        # Something else takes the slot after the first prediction.
+        calls.append(args)
+        if len(calls) > 1:
+            raise RunnerBusyError()
+        return predict(*args, **kwargs)
+
+    with mock.patch.object(runner, "predict", predict_once):
+        resp = client.post(
+            "/predictions/batch",
+            json={"inputs": [{"num": 1}, {"num": 2}, {"num": 3}]},
+        )
+    assert resp.status_code == 200
+    # There's still a result for every input, so they can be matched up.
+    assert resp.json()["results"] == [
+        match({"status": "succeeded", "output": 1, "input": {"num": 1}}),
+        {"status": "failed", "error": "busy"},
+        {"status": "failed", "error": "busy"},
+    ]
+
+
 @uses_predictor("input_integer_default")
 def test_default_int_input(client, match):
     resp = client.post("/predictions", json={"input": {}})
@@ -122,6 +332,20 @@ def test_path_input_data_url(client, match):
     assert resp.status_code == 200
 
 
+@pytest.mark.parametrize(
+    "url",
+    [
+        "data:text/plain;base64",
+        "data:text/plain;base64,YmF",
+    ],
+)
+@uses_predictor("input_path")
+def test_path_input_malformed_data_url(client, url):
+    resp = client.post("/predictions", json={"input": {"path": url}})
+    assert resp.status_code == 422
+    assert "is not a valid data URL" in resp.text
+
+
 @uses_predictor("input_path_2")
 def test_path_temporary_files_are_removed(client, match):
     resp = client.post(
@@ -148,6 +372,72 @@ def test_path_input_with_http_url(client, match):
     assert resp.json() == match({"output": "txt hello", "status": "succeeded"})
 
 
+@uses_predictor_with_client_options(
+    "input_path", env={"COG_MAX_INPUT_DOWNLOAD_SIZE": "5"}
+)
+def test_path_input_with_http_url_within_size_limit(client, httpserver, match):
+    httpserver.expect_request("/foo.txt").respond_with_data("hello")
+    resp = client.post(
+        "/predictions",
+        json={"input": {"path": httpserver.url_for("/foo.txt")}},
+    )
+    assert resp.json() == match({"output": "txt hello", "status": "succeeded"})
+
+
+@uses_predictor_with_client_options(
+    "input_path", env={"COG_MAX_INPUT_DOWNLOAD_SIZE": "4"}
+)
+def test_path_input_with_http_url_too_large(client, httpserver, match):
+    httpserver.expect_request("/foo.txt").respond_with_data("hello")
+    resp = client.post(
+        "/predictions",
+        json={"input": {"path": httpserver.url_for("/foo.txt")}},
+    )
+    assert resp.json() == match({"status": "failed"})
+    assert "is larger than the limit of 4 bytes" in resp.json()["error"]
+
+
+@uses_predictor_with_client_options(
+    "input_path", env={"COG_INPUT_DOWNLOAD_TIMEOUT": "0.1"}
+)
+def test_path_input_with_http_url_timeout(client, httpserver, match):
+    def slow_response(request):
+        time.sleep(0.5)
+        return Response("hello")
+
+    httpserver.expect_request("/foo.txt").respond_with_handler(slow_response)
+    resp = client.post(
+        "/predictions",
+        json={"input": {"path": httpserver.url_for("/foo.txt")}},
+    )
+    assert resp.json() == match({"status": "failed"})
+    assert "timed out" in resp.json()["error"]
+
+
+@uses_predictor("input_path")
+def test_path_input_with_registered_download_backend(client, match):
+    def open_s3_url(url):
+        assert url == "s3://my-bucket/foo.txt"
+        return io.BytesIO(b"hello from s3")
+
+    with mock.patch.dict("cog.types._download_backends", {"s3": open_s3_url}):
+        resp = client.post(
+            "/predictions",
+            json={"input": {"path": "s3://my-bucket/foo.txt"}},
+        )
+    assert resp.json() == match({"output": "txt hello from s3", "status": "succeeded"})
+
+
+@uses_predictor("input_path")
+def test_path_input_with_unknown_url_scheme(client):
+    resp = client.post(
+        "/predictions",
+        json={"input": {"path": "ftp://example.com/foo.txt"}},
+    )
+    assert resp.status_code == 422
+    assert "'ftp' is not a valid URL scheme" in resp.text
+
+
 @uses_predictor("input_file")
 def test_file_bad_input(client):
     resp = client.post(
@@ -174,6 +464,43 @@ def test_multiple_arguments(client, match):
     assert resp.json() == match({"output": "baz 50 wibble", "status": "succeeded"})
 
 
+@uses_predictor("input_multiple")
+def test_multipart_prediction(client, match):
+    resp = client.post(
+        "/predictions",
+        data={"input": json.dumps({"text": "baz", "num1": 5})},
+        files={"path": ("wibble.txt", b"wibble", "text/plain")},
+    )
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "baz 50 wibble", "status": "succeeded"})
+
+
+@uses_predictor("input_path")
+def test_multipart_prediction_passes_a_path(client, match):
+    resp = client.post(
+        "/predictions", files={"path": ("hello.txt", b"hello", "text/plain")}
+    )
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "txt hello", "status": "succeeded"})
+
+
+@uses_predictor("input_path")
+def test_multipart_prediction_rejects_unexpected_parts(client):
+    resp = client.post("/predictions", data={"path": "hello"})
+    assert resp.status_code == 422
+    assert resp.json() == {
+        "detail": "Unexpected part 'path': only 'input' and files are allowed"
+    }
+
+
+@uses_predictor_with_client_options("input_path", max_body_size=100)
+def test_multipart_prediction_body_size_limit(client):
+    resp = client.post(
+        "/predictions", files={"path": ("big.txt", b"a" * 200, "text/plain")}
+    )
+    assert resp.status_code == 413
+
+
 @uses_predictor("input_ge_le")
 def test_gt_lt(client):
     resp = client.post("/predictions", json={"input": {"num": 2}})
@@ -193,12 +520,90 @@ def test_gt_lt(client):
     assert resp.status_code == 200
 
 
+CONSTRAINT_VIOLATIONS = [
+    (
+        {"count": 0},
+        "ensure this value is greater than or equal to 1",
+        "value_error.number.not_ge",
+    ),
+    (
+        {"count": 11},
+        "ensure this value is less than or equal to 10",
+        "value_error.number.not_le",
+    ),
+    (
+        {"text": "a"},
+        "ensure this value has at least 2 characters",
+        "value_error.any_str.min_length",
+    ),
+    (
+        {"text": "abcdef"},
+        "ensure this value has at most 5 characters",
+        "value_error.any_str.max_length",
+    ),
+    (
+        {"items": []},
+        "ensure this value has at least 1 items",
+        "value_error.list.min_items",
+    ),
+    (
+        {"items": ["a", "b", "c", "d"]},
+        "ensure this value has at most 3 items",
+        "value_error.list.max_items",
+    ),
+    (
+        {"code": "ABC"},
+        'string does not match regex "^[a-z]+$"',
+        "value_error.str.regex",
+    ),
+]
+
+
+@pytest.mark.parametrize("input,msg,type", CONSTRAINT_VIOLATIONS)
+@uses_predictor("input_constraints")
+def test_constraint_violations(client, input, msg, type):
+    resp = client.post("/predictions", json={"input": input})
+    assert resp.status_code == 422
+    [error] = resp.json()["detail"]
+    [name] = input.keys()
+    assert error["loc"] == ["body", "input", name]
+    assert error["msg"] == msg
+    assert error["type"] == type
+
+
+@pytest.mark.parametrize(
+    "input",
+    [
+        {"count": 1},
+        {"count": 10},
+        {"text": "ab"},
+        {"text": "abcde"},
+        {"items": ["a"]},
+        {"items": ["a", "b", "c"]},
+        {"code": "xyz"},
+    ],
+)
+@uses_predictor("input_constraints")
+def test_constraints_at_bounds(client, input):
+    resp = client.post("/predictions", json={"input": input})
+    assert resp.status_code == 200
+
+
 @uses_predictor("input_choices")
 def test_choices_str(client):
     resp = client.post("/predictions", json={"input": {"text": "foo"}})
     assert resp.status_code == 200
     resp = client.post("/predictions", json={"input": {"text": "baz"}})
     assert resp.status_code == 422
+    [error] = resp.json()["detail"]
+    assert error["loc"] == ["body", "input", "text"]
+    assert "'foo', 'bar'" in error["msg"]
+
+
+@uses_predictor("input_choices")
+def test_choices_str_is_case_sensitive(client):
+    resp = client.post("/predictions", json={"input": {"text": "Foo"}})
+    assert resp.status_code == 422
 
 
 @uses_predictor("input_choices_integer")
@@ -207,6 +612,9 @@ def test_choices_int(client):
     assert resp.status_code == 200
     resp = client.post("/predictions", json={"input": {"x": 3}})
     assert resp.status_code == 422
+    [error] = resp.json()["detail"]
+    assert error["loc"] == ["body", "input", "x"]
+    assert "1, 2" in error["msg"]
 
 
 @uses_predictor("input_union_string_or_list_of_strings")
@@ -242,6 +650,50 @@ def test_union_integers(client):
     assert resp.status_code == 422
 
 
+@uses_predictor("input_union_string_or_integer")
+def test_union_string_or_integer(client):
+    resp = client.post("/predictions", json={"input": {"value": "abc"}})
+    assert resp.status_code == 200
+    assert resp.json()["output"] == "str abc NoneType"
+
+    # Numbers aren't converted to the first member of the union.
+    resp = client.post("/predictions", json={"input": {"value": 123}})
+    assert resp.status_code == 200
+    assert resp.json()["output"] == "int 123 NoneType"
+
+    resp = client.post("/predictions", json={"input": {"value": 1, "other": "x"}})
+    assert resp.status_code == 200
+    assert resp.json()["output"] == "int 1 str"
+
+
+@uses_predictor("input_union_string_or_integer")
+def test_union_string_or_integer_rejects_other_types(client):
+    for value in (True, 1.5, ["abc"]):
+        resp = client.post("/predictions", json={"input": {"value": value}})
+        assert resp.status_code == 422
+        [error] = resp.json()["detail"]
+        assert error["loc"] == ["body", "input", "value"]
+        assert error["msg"] == "value must be one of these types: str, int"
+
+    resp = client.post("/predictions", json={"input": {"value": 1, "other": False}})
+    assert resp.status_code == 422
+
+
+@uses_predictor("input_optional")
+def test_optional_string_is_still_converted(client, match):
+    # An Optional of one type isn't checked strictly like other unions.
+    resp = client.post("/predictions", json={"input": {"text": "a", "suffix": 1}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "a1", "status": "succeeded"})
+
+
+@uses_predictor("input_optional_integer")
+def test_optional_integer_is_still_converted(client, match):
+    resp = client.post("/predictions", json={"input": {"num": "3"}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "int 3", "status": "succeeded"})
+
+
 @uses_predictor("input_secret")
 def test_secret_str(client, match):
     resp = client.post("/predictions", json={"input": {"secret": "foo"}})
@@ -252,6 +704,13 @@ def test_secret_str(client, match):
     assert resp.status_code == 422
 
 
+@uses_predictor("input_secret")
+def test_secret_not_echoed_in_validation_error(client):
+    resp = client.post("/predictions", json={"input": {"secret": ["hunter2"]}})
+    assert resp.status_code == 422
+    assert "hunter2" not in resp.text
+
+
 def test_untyped_inputs():
     config = {"predict": _fixture_path("input_untyped")}
     app = create_app(
@@ -279,3 +738,47 @@ def test_input_with_unsupported_type():
         "TypeError: Unsupported input type input_unsupported_type"
         in app.state.setup_result.logs
     )
+
+
+def test_input_with_invalid_regex():
+    config = {"predict": _fixture_path("input_invalid_regex")}
+    app = create_app(config=config, shutdown_event=threading.Event())
+    assert app.state.health == Health.SETUP_FAILED
+    assert app.state.setup_result.status == schema.Status.FAILED
+    assert "unterminated character set" in app.state.setup_result.logs
+
+
+@uses_predictor("input_list_items")
+def test_list_input_min_and_max_items(client, match, static_schema):
+    schema = client.get("/openapi.json").json()
+    assert schema == static_schema
+    assert schema["components"]["schemas"]["Input"]["properties"]["tags"] == {
+        "title": "Tags",
+        "type": "array",
+        "items": {"type": "string"},
+        "minItems": 2,
+        "maxItems": 3,
+        "x-order": 0,
+    }
+
+    resp = client.post("/predictions", json={"input": {"tags": ["a", "b"]}})
+    assert resp.status_code == 200
+    assert resp.json() == match({"output": "a,b", "status": "succeeded"})
+
+    resp = client.post("/predictions", json={"input": {"tags": ["a"]}})
+    assert resp.status_code == 422
+    assert resp.json()["detail"][0]["type"] == "value_error.list.min_items"
+
+    resp = client.post("/predictions", json={"input": {"tags": ["a"] * 4}})
+    assert resp.status_code == 422
+    assert resp.json()["detail"][0]["type"] == "value_error.list.max_items"
+
+
+def test_min_items_on_non_list_input():
+    config = {"predict": _fixture_path("input_min_items_not_list")}
+    app = create_app(config=config, shutdown_event=threading.Event())
+    assert app.state.health == Health.SETUP_FAILED
+    assert (
+        "The input text uses min_items or max_items, which can only be used with list types."
+        in app.state.setup_result.logs
+    )
@@ -72,6 +72,27 @@ def test_output_file_to_http_with_upload_url_specified(client, match):
     assert res.status_code == 200
 
 
+@uses_predictor("output_tuple")
+def test_output_tuple(client, match):
+    res = client.post("/predictions")
+    assert res.status_code == 200
+    assert res.json() == match(
+        {
+            "status": "succeeded",
+            "output": ["data:text/plain;base64,aGVsbG8=", "caption"],  # hello
+        }
+    )
+
+    schema = client.get("/openapi.json").json()
+    assert schema["components"]["schemas"]["Output"] == {
+        "title": "Output",
+        "type": "array",
+        "items": [{"type": "string", "format": "uri"}, {"type": "string"}],
+        "minItems": 2,
+        "maxItems": 2,
+    }
+
+
 @uses_predictor("output_path_image")
 def test_output_path(client):
     res = client.post("/predictions")
@@ -82,6 +103,13 @@ def test_output_path(client):
     assert len(base64.b64decode(b64data)) == 195894
 
 
+@uses_predictor("output_path_content_type")
+def test_output_path_with_explicit_content_type(client):
+    res = client.post("/predictions")
+    assert res.status_code == 200
+    assert res.json()["output"] == "data:image/png;base64,aGVsbG8="
+
+
 @responses.activate
 @uses_predictor("output_path_text")
 def test_output_path_to_http(client, match):
@@ -106,6 +134,17 @@ def test_output_path_to_http(client, match):
     assert res.status_code == 200
 
 
+@uses_predictor("output_path_missing")
+def test_output_path_missing(client, match):
+    res = client.post("/predictions")
+    assert res.status_code == 200
+    assert res.json() == match({"status": "failed"})
+    assert res.json()["error"].startswith(
+        "Predictor returned a path that does not exist: /"
+    )
+    assert res.json()["error"].endswith("never-written.txt")
+
+
 @uses_predictor("output_numpy")
 def test_json_output_numpy(client, match):
     resp = client.post("/predictions")
@@ -138,3 +177,30 @@ def test_iterator_of_list_of_complex_output(client, match):
         }
     )
     assert resp.status_code == 200
+
+
+@uses_predictor_with_client_options("output_size", gzip_min_size=1000)
+def test_large_responses_are_compressed(client):
+    headers = {"Accept-Encoding": "gzip"}
+
+    resp = client.post("/predictions", json={"input": {"size": 10}}, headers=headers)
+    assert resp.status_code == 200
+    assert "content-encoding" not in resp.headers
+
+    resp = client.post(
+        "/predictions", json={"input": {"size": 10000}}, headers=headers
+    )
+    assert resp.status_code == 200
+    assert resp.headers["content-encoding"] == "gzip"
+    assert resp.json()["output"] == "x" * 10000
+
+
+@uses_predictor("output_size")
+def test_responses_are_not_compressed_by_default(client):
+    resp = client.post(
+        "/predictions",
+        json={"input": {"size": 10000}},
+        headers={"Accept-Encoding": "gzip"},
+    )
+    assert resp.status_code == 200
+    assert "content-encoding" not in resp.headers
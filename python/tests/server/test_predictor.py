@@ -11,6 +11,8 @@ from cog.predictor import (
 )
 
 PREDICTOR_FIXTURES = [
+    ("decorated_predict", "Predictor", "predict"),
+    ("decorated_predict_stacked", "Predictor", "predict"),
     ("input_choices", "Predictor", "predict"),
     ("input_choices_integer", "Predictor", "predict"),
     ("input_file", "Predictor", "predict"),
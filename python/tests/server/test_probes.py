@@ -1,10 +1,11 @@
 import logging
 import os
+import sys
 import tempfile
 from unittest import mock
 
 import pytest
-from cog.server.probes import ProbeHelper
+from cog.server.probes import ProbeHelper, rss_bytes
 
 
 @pytest.fixture
@@ -53,3 +54,32 @@ def test_no_exception_when_probe_dir_exists(tmpdir, caplog):
     p.ready()
 
     assert "Failed to create cog runtime state directory" in caplog.text
+
+
+def test_ready_file_from_env(tmpdir):
+    ready_file = os.path.join(tmpdir, "probes", "cog-ready")
+    with mock.patch.dict(os.environ, {"COG_READY_FILE": ready_file}):
+        p = ProbeHelper()
+
+    p.ready()
+
+    assert os.path.isfile(ready_file)
+
+
+@mock.patch.dict(os.environ, {"KUBERNETES_SERVICE_HOST": "0.0.0.0"})
+def test_ready_file_argument_overrides_root(tmpdir):
+    ready_file = os.path.join(tmpdir, "cog-ready")
+    p = ProbeHelper(root=os.path.join(tmpdir, "unused"), ready_file=ready_file)
+
+    p.ready()
+
+    assert os.listdir(tmpdir) == ["cog-ready"]
+
+
+@pytest.mark.skipif(sys.platform != "linux", reason="requires /proc")
+def test_rss_bytes():
+    assert rss_bytes("self") > 0
+
+
+def test_rss_bytes_for_missing_process():
+    assert rss_bytes("does-not-exist") is None
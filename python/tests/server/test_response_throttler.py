@@ -33,3 +33,23 @@ def test_nonzero_internal():
     time.sleep(0.3)
 
     assert throttler.should_send_response({"status": Status.PROCESSING})
+
+
+def test_status_change():
+    throttler = ResponseThrottler(response_interval=10)
+
+    assert throttler.should_send_response({"status": Status.STARTING})
+    throttler.update_last_sent_response_time(Status.STARTING)
+    assert not throttler.should_send_response({"status": Status.STARTING})
+    assert throttler.should_send_response({"status": Status.PROCESSING})
+    throttler.update_last_sent_response_time(Status.PROCESSING)
+    assert not throttler.should_send_response({"status": Status.PROCESSING})
+
+
+def test_nothing_after_terminal_status():
+    throttler = ResponseThrottler(response_interval=0)
+
+    assert throttler.should_send_response({"status": Status.SUCCEEDED})
+    throttler.update_last_sent_response_time(Status.SUCCEEDED)
+    assert not throttler.should_send_response({"status": Status.PROCESSING})
+    assert throttler.should_send_response({"status": Status.FAILED})
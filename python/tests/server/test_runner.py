@@ -1,24 +1,37 @@
 import os
+import sys
 import threading
-from datetime import datetime
+from datetime import datetime, timedelta, timezone
 from unittest import mock
 
 import pytest
+import structlog
+from cog import Path
 from cog.schema import PredictionRequest, PredictionResponse, Status, WebhookEvent
 from cog.server.eventtypes import (
     Done,
     Heartbeat,
     Log,
+    PredictionMetric,
     PredictionOutput,
     PredictionOutputType,
 )
+from cog.server.exceptions import FatalWorkerException
 from cog.server.runner import (
+    LOGS_TRUNCATED_MARKER,
     PredictionEventHandler,
     PredictionRunner,
     RunnerBusyError,
+    SlotStats,
     UnknownPredictionError,
     predict,
 )
+from cog.server.telemetry import (
+    add_span_listener,
+    make_trace_context,
+    remove_span_listener,
+    trace_context,
+)
 
 
 def _fixture_path(name):
@@ -53,6 +66,39 @@ def test_prediction_runner_setup():
         runner.shutdown()
 
 
+def test_prediction_runner_setup_writes_ready_file(tmp_path):
+    ready_file = tmp_path / "ready"
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("sleep"), shutdown_event=threading.Event()
+    )
+    try:
+        with mock.patch.dict(os.environ, {"COG_READY_FILE": str(ready_file)}):
+            result = runner.setup().get(5)
+
+        assert result.status == Status.SUCCEEDED
+        assert ready_file.is_file()
+    finally:
+        runner.shutdown()
+
+
+def test_prediction_runner_setup_fails_without_gpu():
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("sleep"),
+        shutdown_event=threading.Event(),
+        gpu_wait_timeout=0.1,
+    )
+    try:
+        with mock.patch(
+            "cog.server.runner.gpu_available", return_value=False
+        ), mock.patch("cog.server.runner.GPU_POLL_INTERVAL", 0.01):
+            result = runner.setup().get(5)
+
+        assert result.status == Status.FAILED
+        assert result.logs == "Error: no GPU became available within 0.1 seconds\n"
+    finally:
+        runner.shutdown()
+
+
 def test_prediction_runner(runner):
     request = PredictionRequest(input={"sleep": 0.1})
     _, async_result = runner.predict(request)
@@ -65,6 +111,80 @@ def test_prediction_runner(runner):
     assert isinstance(response.completed_at, datetime)
 
 
+@pytest.mark.parametrize(
+    "traceparent,trace_id",
+    [
+        (
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "4bf92f3577b34da6a3ce929d0e0e4736",
+        ),
+        ("traceparent-123", None),
+    ],
+)
+def test_prediction_runner_logs_include_trace_id(runner, traceparent, trace_id):
+    cap = structlog.testing.LogCapture()
+    config = structlog.get_config()
+    structlog.configure(processors=[structlog.contextvars.merge_contextvars, cap])
+    try:
+        request = PredictionRequest(id="abcd1234", input={"sleep": 0})
+        with trace_context(make_trace_context(traceparent=traceparent)):
+            _, async_result = runner.predict(request)
+        async_result.get(timeout=1)
+    finally:
+        structlog.configure(**config)
+
+    entries = {e["event"]: e for e in cap.entries}
+    for event in ("starting prediction", "prediction succeeded"):
+        assert entries[event]["prediction_id"] == "abcd1234"
+        assert entries[event].get("trace_id") == trace_id
+
+
+def test_prediction_runner_logs_include_slot_index(runner):
+    cap = structlog.testing.LogCapture()
+    config = structlog.get_config()
+    structlog.configure(processors=[structlog.contextvars.merge_contextvars, cap])
+    try:
+        for prediction_id in ("abcd1234", "5678efgh"):
+            request = PredictionRequest(id=prediction_id, input={"sleep": 0})
+            _, async_result = runner.predict(request)
+            async_result.get(timeout=1)
+    finally:
+        structlog.configure(**config)
+
+    entries = [e for e in cap.entries if "prediction_id" in e]
+    assert {e["prediction_id"] for e in entries} == {"abcd1234", "5678efgh"}
+    assert all(e["slot_index"] == 0 for e in entries)
+
+
+def test_prediction_runner_spans():
+    spans = []
+    add_span_listener(spans.append)
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("sleep"), shutdown_event=threading.Event()
+    )
+    try:
+        runner.setup().get(5)
+
+        traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        request = PredictionRequest(id="abcd1234", input={"sleep": 0.1})
+        with trace_context(make_trace_context(traceparent=traceparent)):
+            _, async_result = runner.predict(request)
+        async_result.get(timeout=1)
+    finally:
+        runner.shutdown()
+        remove_span_listener(spans.append)
+
+    assert [s.name for s in spans] == ["setup", "predict"]
+    assert spans[0].attributes == {"status": "succeeded"}
+    assert spans[1].attributes == {
+        "prediction_id": "abcd1234",
+        "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736",
+        "status": "succeeded",
+    }
+    assert spans[1].duration >= 0.1
+    assert spans[1].start_time >= spans[0].start_time
+
+
 def test_prediction_runner_called_while_busy(runner):
     request = PredictionRequest(input={"sleep": 0.1})
     _, async_result = runner.predict(request)
@@ -143,12 +263,206 @@ def test_prediction_runner_cancel_by_mismatched_id(runner):
     assert response.status == "succeeded"
 
 
+def test_prediction_runner_wait_for_idle(runner):
+    assert runner.wait_for_idle(timeout=0)
+
+    _, async_result = runner.predict(PredictionRequest(input={"sleep": 0.5}))
+    assert not runner.wait_for_idle(timeout=0.1)
+    assert runner.wait_for_idle(timeout=5)
+    assert async_result.ready()
+
+
+def test_prediction_runner_reload(runner):
+    pid = runner._worker.pid()
+
+    result = runner.reload().get(5)
+    assert result.status == Status.SUCCEEDED
+    assert runner._worker.pid() != pid
+
+    _, async_result = runner.predict(PredictionRequest(input={"sleep": 0.1}))
+    response = async_result.get(timeout=5)
+    assert response.status == "succeeded"
+
+
+def test_prediction_runner_reload_refuses_while_busy(runner):
+    runner.predict(PredictionRequest(input={"sleep": 0.5}))
+
+    with pytest.raises(RunnerBusyError):
+        runner.reload()
+
+
+def test_prediction_runner_fails_prediction_for_s3_url_that_cant_be_downloaded():
+    class NoSuchKey(Exception):
+        pass
+
+    boto3 = mock.Mock()
+    boto3.client.return_value.get_object.side_effect = NoSuchKey("no such key")
+
+    shutdown_event = threading.Event()
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("input_path"), shutdown_event=shutdown_event
+    )
+    try:
+        runner.setup().get(5)
+
+        with mock.patch.dict(sys.modules, {"boto3": boto3}):
+            request = PredictionRequest(
+                input={"path": Path.validate("s3://my-bucket/missing.txt")}
+            )
+            _, async_result = runner.predict(request)
+            response = async_result.get(timeout=5)
+
+        assert response.status == Status.FAILED
+        assert "Failed to download s3://my-bucket/missing.txt" in response.error
+        assert "no such key" in response.error
+        # Only the prediction fails, not the server.
+        assert not shutdown_event.is_set()
+        assert not runner.is_busy()
+    finally:
+        runner.shutdown()
+
+
+def test_prediction_runner_slot_stats():
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("crash_in_predict"),
+        shutdown_event=threading.Event(),
+    )
+    try:
+        runner.setup().get(5)
+        assert runner.slot_stats() == SlotStats(total=1, idle=1, busy=0, defunct=0)
+
+        _, async_result = runner.predict(PredictionRequest(input={}))
+        async_result.get(timeout=5)
+        assert runner.slot_stats() == SlotStats(total=1, idle=1, busy=0, defunct=0)
+
+        _, async_result = runner.predict(PredictionRequest(input={"crash": True}))
+        with pytest.raises(FatalWorkerException):
+            async_result.get(timeout=5)
+        assert runner.slot_stats() == SlotStats(total=1, idle=0, busy=0, defunct=1)
+        assert runner.worker_rss_bytes() is None
+    finally:
+        runner.shutdown()
+
+
+def test_prediction_runner_slot_stats_while_busy(runner):
+    _, async_result = runner.predict(PredictionRequest(input={"sleep": 0.5}))
+    assert runner.slot_stats() == SlotStats(total=1, idle=0, busy=1, defunct=0)
+
+    async_result.get(timeout=1)
+    assert runner.slot_stats() == SlotStats(total=1, idle=1, busy=0, defunct=0)
+
+
+def test_prediction_runner_poisons_slot_that_is_never_released():
+    shutdown_event = threading.Event()
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("sleep"),
+        shutdown_event=shutdown_event,
+        slot_release_timeout=0.2,
+    )
+    release = threading.Event()
+
+    # The prediction completes, but sending its final webhook never returns.
+    def stuck_webhook(response, event):
+        if event == WebhookEvent.COMPLETED:
+            release.wait()
+
+    try:
+        runner.setup().get(5)
+        with mock.patch(
+            "cog.server.runner.webhook_caller_filtered", return_value=stuck_webhook
+        ):
+            response, async_result = runner.predict(
+                PredictionRequest(
+                    input={"sleep": 0}, webhook="https://example.com/webhook"
+                )
+            )
+
+        for _ in range(50):
+            if shutdown_event.is_set():
+                break
+            runner.is_busy()
+            shutdown_event.wait(0.1)
+
+        assert response.status == Status.SUCCEEDED
+        assert not async_result.ready()
+        assert shutdown_event.is_set()
+        assert runner.slot_stats() == SlotStats(total=1, idle=0, busy=0, defunct=1)
+    finally:
+        release.set()
+        runner.shutdown()
+
+
+@pytest.mark.skipif(sys.platform != "linux", reason="requires /proc")
+def test_prediction_runner_worker_rss_bytes(runner):
+    assert runner.worker_rss_bytes() > 0
+
+
+def test_prediction_runner_restarts_crashed_worker():
+    shutdown_event = threading.Event()
+    restarts = []
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("crash_in_predict"),
+        shutdown_event=shutdown_event,
+        max_worker_restarts=1,
+        on_worker_restart=restarts.append,
+    )
+    try:
+        runner.setup().get(5)
+
+        _, async_result = runner.predict(PredictionRequest(input={"crash": True}))
+        with pytest.raises(FatalWorkerException):
+            async_result.get(timeout=5)
+
+        assert len(restarts) == 1
+        assert restarts[0].get(timeout=5).status == Status.SUCCEEDED
+
+        _, async_result = runner.predict(PredictionRequest(input={}))
+        response = async_result.get(timeout=5)
+        assert response.status == "succeeded"
+        assert response.output == "ok"
+        assert not shutdown_event.is_set()
+    finally:
+        runner.shutdown()
+
+
+def test_prediction_runner_stops_restarting_worker_that_keeps_crashing():
+    restarts = []
+    runner = PredictionRunner(
+        predictor_ref=_fixture_path("crash_in_predict"),
+        shutdown_event=threading.Event(),
+        max_worker_restarts=1,
+        on_worker_restart=restarts.append,
+    )
+    try:
+        runner.setup().get(5)
+
+        for _ in range(2):
+            _, async_result = runner.predict(PredictionRequest(input={"crash": True}))
+            with pytest.raises(FatalWorkerException):
+                async_result.get(timeout=5)
+            restarts[-1].get(timeout=5)
+
+        assert len(restarts) == 2
+        result = restarts[1].get(timeout=5)
+        assert result.status == Status.FAILED
+        assert "crashed too many times" in result.logs
+    finally:
+        runner.shutdown()
+
+
 # list of (events, calls)
 PREDICT_TESTS = [
     ([Heartbeat()], []),
     ([Done()], [mock.call.succeeded()]),
     ([Done(canceled=True)], [mock.call.canceled()]),
-    ([Done(error=True, error_detail="foo")], [mock.call.failed(error="foo")]),
+    (
+        [Done(error=True, error_detail="foo")],
+        [mock.call.failed(error="foo", error_traceback=None)],
+    ),
+    (
+        [Done(error=True, error_detail="foo", error_traceback="Traceback...")],
+        [mock.call.failed(error="foo", error_traceback="Traceback...")],
+    ),
     ([Log(source="stdout", message="help")], [mock.call.append_logs("help")]),
     (
         [PredictionOutputType(multi=False), PredictionOutput(payload="hello world")],
@@ -178,6 +492,10 @@ PREDICT_TESTS = [
         [PredictionOutput(payload="hello world"), Done()],
         [mock.call.failed(error="Predictor returned unexpected output")],
     ),
+    (
+        [PredictionMetric(name="tokens", value=42)],
+        [mock.call.set_metric("tokens", 42)],
+    ),
 ]
 
 
@@ -265,13 +583,86 @@ def test_prediction_event_handler_webhook_sender(match):
                 "output": ["elephant", "duck"],
                 "logs": "running a prediction\nstill running\n",
                 "status": "succeeded",
-                "metrics": {"predict_time": mock.ANY},
+                "metrics": {"predict_time": mock.ANY, "time_in_queue": 0.0},
             }
         ),
         WebhookEvent.COMPLETED,
     )
 
 
+def test_prediction_event_handler_time_in_queue():
+    s = mock.Mock()
+    created_at = datetime.now(tz=timezone.utc) - timedelta(seconds=5)
+    p = PredictionResponse(input={"hello": "there"}, created_at=created_at)
+    h = PredictionEventHandler(p, webhook_sender=s)
+
+    h.succeeded()
+
+    [payload, event] = s.call_args.args
+    assert event == WebhookEvent.COMPLETED
+    assert payload["metrics"]["predict_time"] >= 0
+    assert payload["metrics"]["time_in_queue"] >= 5
+
+
+def test_prediction_event_handler_custom_metrics(match):
+    s = mock.Mock()
+    p = PredictionResponse(input={"hello": "there"})
+    h = PredictionEventHandler(p, webhook_sender=s)
+
+    h.set_metric("tokens", 42)
+    # Predictors can't override the metrics we measure ourselves.
+    h.set_metric("predict_time", -1)
+    h.succeeded()
+
+    s.assert_called_with(
+        match(
+            {
+                "status": "succeeded",
+                "metrics": {
+                    "tokens": 42,
+                    "predict_time": mock.ANY,
+                    "time_in_queue": 0.0,
+                },
+            }
+        ),
+        WebhookEvent.COMPLETED,
+    )
+    assert p.metrics["predict_time"] >= 0
+
+
+def test_prediction_event_handler_caps_logs():
+    s = mock.Mock()
+    p = PredictionResponse(input={"hello": "there"})
+    h = PredictionEventHandler(p, webhook_sender=s, max_logs_size=20)
+
+    h.append_logs("0123456789\n")
+    h.append_logs("0123456789\n")
+    assert p.logs == "0123456789\n012345678" + LOGS_TRUNCATED_MARKER
+
+    s.reset_mock()
+    h.append_logs("dropped\n")
+    assert p.logs == "0123456789\n012345678" + LOGS_TRUNCATED_MARKER
+    s.assert_not_called()
+
+
+def test_prediction_event_handler_truncates_long_log_lines():
+    p = PredictionResponse(input={"hello": "there"})
+    h = PredictionEventHandler(p, max_log_line_length=5)
+
+    h.append_logs("short\n0123456789\nalso too long")
+    assert p.logs == "short\n01234...\nalso ..."
+
+
+def test_prediction_event_handler_time_in_queue_is_never_negative():
+    created_at = datetime.now(tz=timezone.utc) + timedelta(seconds=5)
+    p = PredictionResponse(input={"hello": "there"}, created_at=created_at)
+    h = PredictionEventHandler(p)
+
+    h.succeeded()
+
+    assert p.metrics["time_in_queue"] == 0.0
+
+
 def test_prediction_event_handler_webhook_sender_intermediate(match):
     s = mock.Mock()
     p = PredictionResponse(input={"hello": "there"})
@@ -346,7 +737,23 @@ def test_prediction_event_handler_webhook_sender_intermediate(match):
 
     s.reset_mock()
     h.canceled()
-    s.assert_called_once_with(match({"status": "canceled"}), WebhookEvent.COMPLETED)
+    s.assert_called_once_with(match({"status": "canceled"}), WebhookEvent.CANCELED)
+
+
+def test_prediction_event_handler_cancel_reason(match):
+    s = mock.Mock()
+    p = PredictionResponse(input={"hello": "there"})
+    h = PredictionEventHandler(p, webhook_sender=s)
+
+    h.cancel_reason = "timeout"
+    s.reset_mock()
+    h.canceled()
+
+    assert p.cancel_reason == "timeout"
+    s.assert_called_once_with(
+        match({"status": "canceled", "cancel_reason": "timeout"}),
+        WebhookEvent.CANCELED,
+    )
 
 
 def test_prediction_event_handler_file_uploads():
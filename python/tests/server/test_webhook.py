@@ -1,8 +1,24 @@
+import base64
+import gzip
+import hashlib
+import hmac
+import json
+import os
+import threading
+import time
+from unittest import mock
+
+import pytest
 import requests
 import responses
 from cog.schema import WebhookEvent
-from cog.server.webhook import webhook_caller, webhook_caller_filtered
-from responses import registries
+from cog.server.webhook import (
+    _parse_gzip_min_size,
+    wait_for_pending_webhooks,
+    webhook_caller,
+    webhook_caller_filtered,
+)
+from responses import matchers, registries
 
 
 @responses.activate
@@ -54,9 +70,129 @@ def test_webhook_caller_terminal_retries():
 
     c({"status": "succeeded", "animal": "giraffe"})
 
+    # retries happen in the background
+    _wait_for(lambda: resps[2].call_count == 1)
+
     assert all(r.call_count == 1 for r in resps)
 
 
+@responses.activate
+def test_webhook_caller_terminal_retry_superseded_by_newer_update():
+    statuses = []
+
+    def callback(request):
+        status = json.loads(request.body)["status"]
+        statuses.append(status)
+        return (500 if status == "failed" else 200, {}, "")
+
+    responses.add_callback(
+        responses.POST, "https://example.com/webhook/123", callback=callback
+    )
+
+    c = webhook_caller("https://example.com/webhook/123")
+    c({"status": "failed", "animal": "giraffe"})
+    c({"status": "succeeded", "animal": "giraffe"})
+
+    # give any (incorrectly) pending retries a chance to happen
+    time.sleep(0.5)
+
+    assert statuses == ["failed", "succeeded"]
+
+
+@responses.activate
+def test_webhook_caller_sends_concurrent_updates_in_order():
+    statuses = []
+
+    def callback(request):
+        status = json.loads(request.body)["status"]
+        if status == "starting":
+            # Slow enough that the next update would overtake it, if it could.
+            time.sleep(0.2)
+        statuses.append(status)
+        return (200, {}, "")
+
+    responses.add_callback(
+        responses.POST, "https://example.com/webhook/123", callback=callback
+    )
+
+    with mock.patch("cog.server.webhook._response_interval", 0):
+        c = webhook_caller("https://example.com/webhook/123")
+        start = threading.Thread(target=c, args=({"status": "starting"},))
+        start.start()
+        time.sleep(0.05)
+        output = threading.Thread(
+            target=c, args=({"status": "processing", "output": ["hello"]},)
+        )
+        output.start()
+        start.join()
+        output.join()
+        c({"status": "succeeded", "output": ["hello"]})
+        # Updates after the prediction has completed are dropped.
+        c({"status": "processing", "logs": "late\n"})
+
+    assert statuses == ["starting", "processing", "succeeded"]
+
+
+@responses.activate
+def test_webhook_caller_sends_queued_updates_in_order():
+    outputs = []
+
+    def callback(request):
+        output = json.loads(request.body)["output"]
+        if not output:
+            # Holds the others up, so they all queue behind it.
+            time.sleep(0.3)
+        outputs.append(output)
+        return (200, {}, "")
+
+    responses.add_callback(
+        responses.POST, "https://example.com/webhook/123", callback=callback
+    )
+
+    with mock.patch("cog.server.webhook._response_interval", 0):
+        c = webhook_caller("https://example.com/webhook/123")
+        threads = []
+        for n in range(5):
+            response = {"status": "processing", "output": ["x"] * n}
+            thread = threading.Thread(target=c, args=(response,))
+            thread.start()
+            threads.append(thread)
+            time.sleep(0.02)
+        for thread in threads:
+            thread.join()
+
+    assert outputs == [["x"] * n for n in range(5)]
+
+
+@responses.activate
+def test_webhook_caller_terminal_gives_up_after_max_attempts():
+    resp = responses.post("https://example.com/webhook/123", status=503)
+
+    with mock.patch("cog.server.webhook._max_attempts", 3):
+        c = webhook_caller("https://example.com/webhook/123")
+        c({"status": "succeeded", "animal": "giraffe"})
+
+        _wait_for(lambda: resp.call_count == 3)
+        time.sleep(0.5)
+
+    assert resp.call_count == 3
+
+
+@responses.activate
+def test_wait_for_pending_webhooks():
+    resp = responses.post("https://example.com/webhook/123", status=503)
+
+    with mock.patch("cog.server.webhook._max_attempts", 3):
+        c = webhook_caller("https://example.com/webhook/123")
+        c({"status": "succeeded", "animal": "giraffe"})
+
+        # The wait is bounded, even if retries are still going.
+        assert not wait_for_pending_webhooks(timeout=0)
+        assert wait_for_pending_webhooks(timeout=5)
+
+    assert resp.call_count == 3
+
+
 @responses.activate
 def test_webhook_includes_user_agent():
     c = webhook_caller("https://example.com/webhook/123")
@@ -96,6 +232,35 @@ def test_webhook_caller_filtered_omits_filtered_events():
     c({"status": "processing", "animal": "giraffe"}, WebhookEvent.LOGS)
 
 
+@responses.activate
+def test_webhook_caller_filtered_sends_canceled_events_for_completed_filter():
+    events = {WebhookEvent.COMPLETED}
+    c = webhook_caller_filtered("https://example.com/webhook/123", events)
+
+    webhook = responses.post("https://example.com/webhook/123", status=200)
+
+    c({"status": "canceled"}, WebhookEvent.CANCELED)
+
+    assert webhook.call_count == 1
+
+
+@responses.activate
+def test_webhook_caller_filtered_can_filter_for_canceled_events_only():
+    events = {WebhookEvent.CANCELED}
+    c = webhook_caller_filtered("https://example.com/webhook/123", events)
+
+    webhook = responses.post(
+        "https://example.com/webhook/123",
+        match=[matchers.json_params_matcher({"status": "canceled"})],
+        status=200,
+    )
+
+    c({"status": "succeeded"}, WebhookEvent.COMPLETED)
+    c({"status": "canceled"}, WebhookEvent.CANCELED)
+
+    assert webhook.call_count == 1
+
+
 @responses.activate
 def test_webhook_caller_connection_errors():
     connerror_resp = responses.Response(
@@ -111,3 +276,107 @@ def test_webhook_caller_connection_errors():
     c = webhook_caller("https://example.com/webhook/123")
     # this should not raise an error
     c({"status": "processing", "animal": "giraffe"})
+
+
+@responses.activate
+def test_webhook_caller_signs_payload():
+    with mock.patch.dict(os.environ, {"WEBHOOK_SIGNING_SECRET": "s3cr3t"}):
+        c = webhook_caller("https://example.com/webhook/123")
+
+    responses.post("https://example.com/webhook/123", status=200)
+
+    c({"status": "processing", "animal": "giraffe"})
+
+    assert len(responses.calls) == 1
+    request = responses.calls[0].request
+    timestamp = request.headers["webhook-timestamp"]
+    signed_content = timestamp.encode("utf-8") + b"." + request.body
+    digest = hmac.new(b"s3cr3t", signed_content, hashlib.sha256).digest()
+    expected = "v1," + base64.b64encode(digest).decode("utf-8")
+    assert request.headers["webhook-signature"] == expected
+    assert json.loads(request.body) == {"status": "processing", "animal": "giraffe"}
+
+
+@responses.activate
+def test_webhook_caller_does_not_sign_without_secret():
+    c = webhook_caller("https://example.com/webhook/123")
+
+    responses.post("https://example.com/webhook/123", status=200)
+
+    c({"status": "processing", "animal": "giraffe"})
+
+    assert len(responses.calls) == 1
+    assert "webhook-signature" not in responses.calls[0].request.headers
+    assert "webhook-timestamp" not in responses.calls[0].request.headers
+
+
+@responses.activate
+def test_webhook_caller_includes_log_cursor():
+    responses.post("https://example.com/webhook/123", status=200)
+
+    with mock.patch("cog.server.webhook._response_interval", 0):
+        c = webhook_caller("https://example.com/webhook/123")
+        c({"status": "processing", "logs": "one\n"})
+        c({"status": "processing", "logs": "one\ntwo\n"})
+        c({"status": "succeeded", "logs": "one\ntwo\nthree\n"})
+
+    bodies = [json.loads(call.request.body) for call in responses.calls]
+    assert [b["log_cursor"] for b in bodies] == [4, 8, 14]
+    assert [b["logs"] for b in bodies] == [
+        "one\n",
+        "one\ntwo\n",
+        "one\ntwo\nthree\n",
+    ]
+
+
+@responses.activate
+def test_webhook_caller_sends_log_deltas():
+    responses.post("https://example.com/webhook/123", status=200)
+
+    with mock.patch("cog.server.webhook._response_interval", 0), mock.patch(
+        "cog.server.webhook._log_deltas", True
+    ):
+        c = webhook_caller("https://example.com/webhook/123")
+        c({"status": "processing", "logs": "one\n"})
+        c({"status": "processing", "logs": "one\ntwo\n"})
+        c({"status": "succeeded", "logs": "one\ntwo\nthree\n"})
+
+    bodies = [json.loads(call.request.body) for call in responses.calls]
+    assert [b["log_cursor"] for b in bodies] == [4, 8, 14]
+    assert [b["logs"] for b in bodies] == ["one\n", "two\n", "three\n"]
+
+
+@responses.activate
+def test_webhook_caller_compresses_large_payloads():
+    responses.post("https://example.com/webhook/123", status=200)
+
+    with mock.patch("cog.server.webhook._gzip_min_size", 100):
+        c = webhook_caller("https://example.com/webhook/123")
+        c({"status": "processing"})
+        c({"status": "succeeded", "output": "x" * 1000})
+
+    small, large = [call.request for call in responses.calls]
+    assert "content-encoding" not in small.headers
+    assert json.loads(small.body) == {"status": "processing"}
+    assert large.headers["content-encoding"] == "gzip"
+    assert json.loads(gzip.decompress(large.body)) == {
+        "status": "succeeded",
+        "output": "x" * 1000,
+    }
+
+
+def _wait_for(condition, timeout=5):
+    deadline = time.time() + timeout
+    while not condition():
+        assert time.time() < deadline, "timed out waiting for condition"
+        time.sleep(0.01)
+
+
+def test_parse_gzip_min_size():
+    assert _parse_gzip_min_size(None) is None
+    assert _parse_gzip_min_size("") is None
+    assert _parse_gzip_min_size("0") == 0
+    assert _parse_gzip_min_size("1024") == 1024
+    for value in ("-1", "1kb", "1.5"):
+        with pytest.raises(ValueError, match="COG_WEBHOOK_GZIP_MIN_SIZE"):
+            _parse_gzip_min_size(value)
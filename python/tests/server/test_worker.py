@@ -1,18 +1,26 @@
 import os
+import sys
+import tempfile
 import time
+from multiprocessing.reduction import ForkingPickler
 from typing import Any, Optional
+from unittest import mock
 
 import pytest
 from attrs import define
+from cog import File
 from cog.server.eventtypes import (
     Done,
     Heartbeat,
     Log,
+    PredictionMetric,
     PredictionOutput,
     PredictionOutputType,
+    PredictionResponseHeader,
+    SpilledPredictionOutput,
 )
 from cog.server.exceptions import FatalWorkerException, InvalidStateException
-from cog.server.worker import Worker
+from cog.server.worker import Worker, _ChildWorker, _load_spilled_output
 from hypothesis import given, settings
 from hypothesis import strategies as st
 from hypothesis.stateful import (
@@ -462,6 +470,363 @@ def test_graceful_shutdown():
         w.terminate()
 
 
+def test_async_generator_outputs_are_streamed():
+    w = Worker(predictor_ref=_fixture_path("async_yield_then_sleep"), tee_output=False)
+
+    try:
+        _process(w.setup())
+
+        result = _process(w.predict({"sleep": 0.1}))
+
+        assert result.output == ["first", "second"]
+        assert not result.done.error
+    finally:
+        w.terminate()
+
+
+def test_async_generator_cancel_keeps_partial_output():
+    """
+    Canceling an async generator prediction should stop it partway through,
+    keeping the outputs it has already produced.
+    """
+    w = Worker(predictor_ref=_fixture_path("async_yield_then_sleep"), tee_output=False)
+
+    try:
+        _process(w.setup())
+
+        outputs = []
+        done = None
+        for event in w.predict({"sleep": 5}, poll=0.01):
+            if isinstance(event, PredictionOutput):
+                outputs.append(event.payload)
+                w.cancel()
+            elif isinstance(event, Done):
+                done = event
+
+        assert outputs == ["first"]
+        assert done.canceled
+
+        # The worker should still be usable afterwards.
+        result = _process(w.predict({"sleep": 0}))
+        assert result.output == ["first", "second"]
+    finally:
+        w.terminate()
+
+
+def test_warmup_runs_after_setup():
+    w = Worker(predictor_ref=_fixture_path("warmup"), tee_output=False)
+
+    try:
+        result = _process(w.setup())
+        assert not result.done.error
+        assert result.stdout == "setting up\nwarming up\n"
+
+        result = _process(w.predict({}))
+        assert result.output is True
+    finally:
+        w.terminate()
+
+
+def test_warmup_failures_are_not_fatal():
+    w = Worker(predictor_ref=_fixture_path("warmup_fails"), tee_output=False)
+
+    try:
+        result = _process(w.setup())
+        assert not result.done.error
+        assert "warmup failed" in result.stderr
+        assert "RuntimeError: out of memory" in result.stderr
+
+        result = _process(w.predict({}))
+        assert result.output == "still works"
+    finally:
+        w.terminate()
+
+
+def test_warmup_failures_can_be_fatal():
+    w = Worker(predictor_ref=_fixture_path("warmup_fails"), tee_output=False)
+
+    try:
+        with mock.patch.dict(os.environ, {"COG_WARMUP_FAILURE_FATAL": "true"}):
+            with pytest.raises(FatalWorkerException):
+                _process(w.setup())
+    finally:
+        w.terminate()
+
+
+def test_record_metric():
+    """
+    Metrics recorded by the predictor should be sent as they're recorded, in
+    order with the outputs around them.
+    """
+    w = Worker(predictor_ref=_fixture_path("record_metric"), tee_output=False)
+
+    try:
+        _process(w.setup())
+
+        events = [
+            e
+            for e in w.predict({"steps": 2})
+            if isinstance(e, (PredictionMetric, PredictionOutput, Done))
+        ]
+
+        assert events == [
+            PredictionMetric(name="input_token_count", value=2),
+            PredictionOutput(payload="step 0"),
+            PredictionMetric(name="steps_completed", value=1),
+            PredictionOutput(payload="step 1"),
+            PredictionMetric(name="steps_completed", value=2),
+            Done(),
+        ]
+    finally:
+        w.terminate()
+
+
+def test_response_header():
+    w = Worker(predictor_ref=_fixture_path("response_header"), tee_output=False)
+
+    try:
+        _process(w.setup())
+
+        events = [
+            e
+            for e in w.predict({})
+            if isinstance(e, (PredictionResponseHeader, PredictionOutput, Done))
+        ]
+
+        assert events == [
+            PredictionResponseHeader(name="Cache-Control", value="max-age=60"),
+            PredictionOutput(payload="hello"),
+            Done(),
+        ]
+    finally:
+        w.terminate()
+
+
+def test_file_input_is_read_as_a_stream():
+    """
+    A File input is validated into a file-like object before it's sent to the
+    worker, and the predictor reads it as a stream rather than a path.
+    """
+    w = Worker(predictor_ref=_fixture_path("input_file"), tee_output=False)
+
+    try:
+        _process(w.setup())
+
+        result = _process(w.predict({"file": File.validate("data:,hello")}))
+
+        assert result.output == b"hello"
+    finally:
+        w.terminate()
+
+
+def test_isolate_cwd():
+    """
+    With isolate_cwd, each prediction should run in its own temporary working
+    directory, so relative writes from different predictions don't collide.
+    """
+    w = Worker(predictor_ref=_fixture_path("cwd"), tee_output=False, isolate_cwd=True)
+
+    try:
+        _process(w.setup())
+
+        result1 = _process(w.predict({}))
+        result2 = _process(w.predict({}))
+
+        assert result1.output != os.getcwd()
+        assert result1.output.startswith(os.path.realpath(tempfile.gettempdir()))
+        assert result1.output != result2.output
+        # Each prediction's working directory is removed once it's finished.
+        assert not os.path.exists(result1.output)
+        assert not os.path.exists(result2.output)
+    finally:
+        w.terminate()
+
+
+def test_isolate_cwd_keeps_output_files():
+    w = Worker(
+        predictor_ref=_fixture_path("cwd_output_path"),
+        tee_output=False,
+        isolate_cwd=True,
+    )
+
+    try:
+        _process(w.setup())
+        result = _process(w.predict({}))
+
+        # The file was moved out of the working directory before it was
+        # removed, so it's still there to be uploaded.
+        assert os.path.isabs(result.output)
+        assert os.path.basename(result.output) == "output.txt"
+        with open(result.output) as f:
+            assert f.read() == "hello"
+    finally:
+        w.terminate()
+
+
+def test_isolate_cwd_keeps_output_directories():
+    w = Worker(
+        predictor_ref=_fixture_path("cwd_output_directory"),
+        tee_output=False,
+        isolate_cwd=True,
+    )
+
+    try:
+        _process(w.setup())
+        result = _process(w.predict({}))
+
+        assert os.path.basename(result.output) == "images"
+        with open(os.path.join(result.output, "1.txt")) as f:
+            assert f.read() == "hello"
+    finally:
+        w.terminate()
+
+
+def test_isolate_cwd_restores_cwd():
+    """
+    The original working directory should be restored after each prediction,
+    even if the prediction fails.
+    """
+    child = _ChildWorker(_fixture_path("cwd"), events=None, isolate_cwd=True)
+    original_cwd = os.getcwd()
+
+    with child._prediction_cwd():
+        assert os.getcwd() != original_cwd
+        assert os.getcwd().startswith(os.path.realpath(tempfile.gettempdir()))
+    assert os.getcwd() == original_cwd
+
+    with pytest.raises(RuntimeError):
+        with child._prediction_cwd():
+            raise RuntimeError("oops")
+    assert os.getcwd() == original_cwd
+
+
+def test_large_outputs_are_spilled_to_a_file():
+    w = Worker(
+        predictor_ref=_fixture_path("output_size"),
+        tee_output=False,
+        max_inline_output_size=1024,
+    )
+
+    with mock.patch(
+        "cog.server.worker._load_spilled_output", wraps=_load_spilled_output
+    ) as load_spilled_output:
+        try:
+            _process(w.setup())
+
+            result = _process(w.predict({"size": 900}))
+            assert result.output == "x" * 900
+            assert load_spilled_output.call_count == 0
+
+            result = _process(w.predict({"size": 1024}))
+            assert result.output == "x" * 1024
+            assert load_spilled_output.call_count == 1
+            spilled_path = load_spilled_output.call_args[0][0]
+            assert not os.path.exists(spilled_path)
+        finally:
+            w.terminate()
+
+
+def test_large_outputs_are_spilled_to_cog_tmpdir(tmp_path):
+    tmpdir = tmp_path / "cog-tmp"
+    w = Worker(
+        predictor_ref=_fixture_path("output_size"),
+        tee_output=False,
+        max_inline_output_size=1024,
+    )
+
+    with mock.patch.dict(os.environ, {"COG_TMPDIR": str(tmpdir)}), mock.patch(
+        "cog.server.worker._load_spilled_output", wraps=_load_spilled_output
+    ) as load_spilled_output:
+        try:
+            _process(w.setup())
+
+            result = _process(w.predict({"size": 2048}))
+            assert result.output == "x" * 2048
+            spilled_path = load_spilled_output.call_args[0][0]
+            assert os.path.dirname(spilled_path) == str(tmpdir)
+        finally:
+            w.terminate()
+
+
+def test_spilled_outputs_from_a_dead_child_are_removed(tmp_path):
+    w = Worker(predictor_ref=_fixture_path("output_size"), tee_output=False)
+    spilled_path = tmp_path / "output.pickle"
+    spilled_path.write_bytes(b"")
+
+    # As if the child had spilled an output and then died before the parent
+    # process read it.
+    w._child._events.send(SpilledPredictionOutput(path=str(spilled_path)))
+    w._child._events.close()
+    w._discard_spilled_outputs()
+
+    assert not spilled_path.exists()
+
+
+def test_outputs_at_the_inline_limit_are_not_spilled():
+    limit = len(ForkingPickler.dumps(PredictionOutput(payload="x" * 1000)))
+    w = Worker(
+        predictor_ref=_fixture_path("output_size"),
+        tee_output=False,
+        max_inline_output_size=limit,
+    )
+
+    with mock.patch(
+        "cog.server.worker._load_spilled_output", wraps=_load_spilled_output
+    ) as load_spilled_output:
+        try:
+            _process(w.setup())
+
+            result = _process(w.predict({"size": 1000}))
+            assert result.output == "x" * 1000
+            assert load_spilled_output.call_count == 0
+
+            result = _process(w.predict({"size": 1001}))
+            assert result.output == "x" * 1001
+            assert load_spilled_output.call_count == 1
+        finally:
+            w.terminate()
+
+
+def test_max_inline_output_size_must_fit_in_a_message():
+    with pytest.raises(ValueError):
+        Worker(predictor_ref=_fixture_path("output_size"), max_inline_output_size=0)
+    with pytest.raises(ValueError):
+        Worker(
+            predictor_ref=_fixture_path("output_size"),
+            max_inline_output_size=2**31,
+        )
+
+
+def test_python_executable_override(tmp_path):
+    """
+    The child process should be started with the configured executable, which
+    may be a wrapper around the real interpreter.
+    """
+    marker = tmp_path / "marker"
+    wrapper = tmp_path / "python"
+    wrapper.write_text(f'#!/bin/sh\ntouch "{marker}"\nexec "{sys.executable}" "$@"\n')
+    wrapper.chmod(0o755)
+
+    w = Worker(
+        predictor_ref=_fixture_path("simple"),
+        tee_output=False,
+        python_executable=str(wrapper),
+    )
+
+    try:
+        result = _process(w.setup())
+        assert not result.done.error
+        assert marker.exists()
+    finally:
+        w.terminate()
+
+
+def test_python_executable_from_environment(monkeypatch):
+    monkeypatch.setenv("COG_PYTHON", "/opt/conda/bin/python")
+    w = Worker(predictor_ref=_fixture_path("simple"))
+    assert w._python_executable == "/opt/conda/bin/python"
+
+
 class WorkerState(RuleBasedStateMachine):
     """
     This is a Hypothesis-driven rule-based state machine test. It is intended
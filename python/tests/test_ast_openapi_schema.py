@@ -0,0 +1,226 @@
+import json
+
+import pytest
+from cog.command.ast_openapi_schema import extract_info, main
+
+PREDICTOR = """
+from cog import BasePredictor
+
+class Predictor(BasePredictor):
+    def predict(self, text: str) -> str:
+        pass
+"""
+
+
+def test_set_input():
+    schema = extract_info(
+        """
+from cog import BasePredictor
+
+class Predictor(BasePredictor):
+    def predict(self, tags: set[str] = {"a", "b"}) -> str:
+        pass
+"""
+    )
+    tags = schema["components"]["schemas"]["Input"]["properties"]["tags"]
+    assert tags["type"] == "array"
+    assert tags["items"] == {"type": "string"}
+    assert tags["uniqueItems"] is True
+    assert sorted(tags["default"]) == ["a", "b"]
+
+
+def test_file_and_path_are_both_uris():
+    schema = extract_info(
+        """
+from cog import BasePredictor, File, Path
+
+class Predictor(BasePredictor):
+    def predict(self, file: File, path: Path) -> File:
+        pass
+"""
+    )
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["file"] == {
+        "title": "File",
+        "type": "string",
+        "format": "uri",
+        "x-order": 0,
+    }
+    assert properties["path"] == {
+        "title": "Path",
+        "type": "string",
+        "format": "uri",
+        "x-order": 1,
+    }
+    assert schema["components"]["schemas"]["Output"] == {
+        "title": "Output",
+        "type": "string",
+        "format": "uri",
+    }
+
+
+def test_annotated_input_matches_default_input():
+    annotated = extract_info(
+        """
+from typing import Annotated
+from cog import BasePredictor, Input
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        prompt: Annotated[str, Input(description="A prompt", max_length=10)],
+        steps: Annotated[int, Input(ge=1)] = 20,
+    ) -> str:
+        pass
+"""
+    )
+    default = extract_info(
+        """
+from cog import BasePredictor, Input
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        prompt: str = Input(description="A prompt", max_length=10),
+        steps: int = Input(default=20, ge=1),
+    ) -> str:
+        pass
+"""
+    )
+    assert annotated == default
+
+
+def test_annotated_input_cannot_also_have_input_default():
+    with pytest.raises(ValueError):
+        extract_info(
+            """
+from typing import Annotated
+from cog import BasePredictor, Input
+
+class Predictor(BasePredictor):
+    def predict(self, steps: Annotated[int, Input(ge=1)] = Input(default=20)) -> str:
+        pass
+"""
+        )
+
+
+def test_input_title_and_passthrough_attributes():
+    schema = extract_info(
+        """
+from cog import BasePredictor, Input, Path
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        prompt: str = Input(title="Prompt text", example="an onion", future=1),
+        image: Path = Input(format="image", example="https://example.com/a.png"),
+    ) -> str:
+        pass
+"""
+    )
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["prompt"]["title"] == "Prompt text"
+    assert properties["prompt"]["example"] == "an onion"
+    # Unknown attributes are ignored.
+    assert "future" not in properties["prompt"]
+    # The type's format wins.
+    assert properties["image"]["format"] == "uri"
+    assert properties["image"]["example"] == "https://example.com/a.png"
+    assert properties["image"]["title"] == "Image"
+
+
+def test_list_input_min_and_max_items():
+    schema = extract_info(
+        """
+from cog import BasePredictor, Input, Path
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        tags: list[str] = Input(min_items=1, max_items=3),
+        images: List[Path] = Input(min_length=2),
+    ) -> str:
+        pass
+"""
+    )
+    properties = schema["components"]["schemas"]["Input"]["properties"]
+    assert properties["tags"]["type"] == "array"
+    assert properties["tags"]["items"] == {"type": "string"}
+    assert properties["tags"]["minItems"] == 1
+    assert properties["tags"]["maxItems"] == 3
+    assert properties["images"]["items"] == {"type": "string", "format": "uri"}
+    assert properties["images"]["minItems"] == 2
+    assert "min_length" not in properties["images"]
+
+
+def test_min_items_on_non_list_input():
+    with pytest.raises(ValueError, match="can only be used with list inputs"):
+        extract_info(
+            """
+from cog import BasePredictor, Input
+
+class Predictor(BasePredictor):
+    def predict(self, text: str = Input(min_items=1)) -> str:
+        pass
+"""
+        )
+
+
+def test_input_when_is_captured():
+    schema = extract_info(
+        """
+from cog import BasePredictor, Input
+
+class Predictor(BasePredictor):
+    def predict(
+        self,
+        mode: str = "simple",
+        steps: int = Input(default=20, when={"field": "mode", "equals": "advanced"}),
+    ) -> str:
+        pass
+"""
+    )
+    steps = schema["components"]["schemas"]["Input"]["properties"]["steps"]
+    assert steps["x-cog-when"] == {"field": "mode", "equals": "advanced"}
+
+
+def test_input_when_must_be_a_dict_literal():
+    with pytest.raises(ValueError, match="Input\\(when=...\\) for 'steps'"):
+        extract_info(
+            """
+from cog import BasePredictor, Input
+
+CONDITION = {"field": "mode", "equals": "advanced"}
+
+class Predictor(BasePredictor):
+    def predict(self, steps: int = Input(default=20, when=CONDITION)) -> str:
+        pass
+"""
+        )
+
+
+def test_output_type_of_none_is_an_error():
+    with pytest.raises(ValueError, match="Unexpected node type"):
+        extract_info(
+            """
+from cog import BasePredictor
+
+class Predictor(BasePredictor):
+    def predict(self, text: str) -> None:
+        pass
+"""
+        )
+
+
+def test_out_writes_schema_to_file(tmp_path, capsys):
+    predictor = tmp_path / "predict.py"
+    predictor.write_text(PREDICTOR)
+    out = tmp_path / "schema.json"
+    out.write_text("stale")
+
+    main([str(predictor), "--out", str(out)])
+
+    assert json.loads(out.read_text()) == extract_info(PREDICTOR)
+    assert capsys.readouterr().out == ""
+    # The temporary file it's written to first is gone.
+    assert sorted(p.name for p in tmp_path.iterdir()) == ["predict.py", "schema.json"]
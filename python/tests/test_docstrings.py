@@ -0,0 +1,47 @@
+from cog.docstrings import parse_parameter_descriptions
+
+
+def test_google_style():
+    docstring = """
+    Upscales an image.
+
+    Args:
+        image (Path): The image
+            to upscale.
+        scale: How much to upscale it.
+
+    Returns:
+        The upscaled image.
+    """
+    assert parse_parameter_descriptions(docstring) == {
+        "image": "The image to upscale.",
+        "scale": "How much to upscale it.",
+    }
+
+
+def test_numpy_style():
+    docstring = """
+    Upscales an image.
+
+    Parameters
+    ----------
+    image : Path
+        The image
+        to upscale.
+    scale
+        How much to upscale it.
+
+    Returns
+    -------
+    Path
+        The upscaled image.
+    """
+    assert parse_parameter_descriptions(docstring) == {
+        "image": "The image to upscale.",
+        "scale": "How much to upscale it.",
+    }
+
+
+def test_no_parameters_section():
+    assert parse_parameter_descriptions("Upscales an image.") == {}
+    assert parse_parameter_descriptions(None) == {}
@@ -1,5 +1,8 @@
+import base64
+import io
 import os
 import tempfile
+import zipfile
 
 import cog
 import numpy as np
@@ -48,6 +51,25 @@ def test_upload_files():
     }
 
 
+def test_upload_files_zips_directories():
+    temp_dir = tempfile.mkdtemp()
+    output_dir = os.path.join(temp_dir, "artifacts")
+    os.makedirs(os.path.join(output_dir, "nested"))
+    with open(os.path.join(output_dir, "a.txt"), "w") as fh:
+        fh.write("a")
+    with open(os.path.join(output_dir, "nested", "b.txt"), "w") as fh:
+        fh.write("b")
+
+    result = upload_files({"path": cog.Path(output_dir)}, upload_file)
+
+    prefix = "data:application/zip;base64,"
+    assert result["path"].startswith(prefix)
+    archive = base64.b64decode(result["path"][len(prefix) :])
+    with zipfile.ZipFile(io.BytesIO(archive)) as zf:
+        assert zf.read("a.txt") == b"a"
+        assert zf.read("nested/b.txt") == b"b"
+
+
 def test_numpy():
     class Model(BaseModel):
         ndarray: np.ndarray
@@ -0,0 +1,51 @@
+import json
+import logging
+
+import pytest
+import structlog
+from cog.logging import setup_logging
+
+
+@pytest.fixture(autouse=True)
+def restore_logging():
+    root = logging.getLogger()
+    handlers = root.handlers[:]
+    level = root.level
+    config = structlog.get_config()
+    yield
+    root.handlers[:] = handlers
+    root.setLevel(level)
+    structlog.configure(**config)
+
+
+def test_json_logs(monkeypatch, capsys):
+    monkeypatch.delenv("LOG_FORMAT", raising=False)
+    setup_logging(log_level=logging.INFO)
+
+    structlog.get_logger("test").info("hello world", answer=42)
+
+    line = json.loads(capsys.readouterr().err)
+    assert line["message"] == "hello world"
+    assert line["severity"] == "INFO"
+    assert line["answer"] == 42
+
+
+def test_logfmt_logs(monkeypatch, capsys):
+    monkeypatch.setenv("LOG_FORMAT", "logfmt")
+    setup_logging(log_level=logging.INFO)
+
+    structlog.get_logger("test").info("hello world", answer=42)
+
+    line = capsys.readouterr().err
+    assert line.startswith('level=info msg="hello world" ')
+    assert " answer=42" in line
+
+
+def test_unknown_log_format_falls_back_to_json(monkeypatch, capsys):
+    monkeypatch.setenv("LOG_FORMAT", "yaml")
+    setup_logging(log_level=logging.INFO)
+
+    warning = json.loads(capsys.readouterr().err)
+    assert warning["message"] == "unknown LOG_FORMAT, using json"
+    assert warning["severity"] == "WARNING"
+    assert warning["log_format"] == "yaml"
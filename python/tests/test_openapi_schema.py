@@ -0,0 +1,32 @@
+import json
+
+import yaml
+from cog.command.openapi_schema import format_schema
+
+SCHEMA = {
+    "openapi": "3.0.2",
+    "info": {"title": "Cog", "version": "0.1.0"},
+    "components": {
+        "schemas": {
+            "Input": {
+                "type": "object",
+                "properties": {
+                    "prompt": {"type": "string", "default": "yes"},
+                    "steps": {"type": "integer", "minimum": 1},
+                    "choices": {"enum": ["on", "off", "null"]},
+                },
+                "required": ["steps"],
+            }
+        }
+    },
+}
+
+
+def test_format_schema_as_json():
+    assert json.loads(format_schema(SCHEMA)) == SCHEMA
+
+
+def test_format_schema_as_yaml():
+    output = format_schema(SCHEMA, "yaml")
+    assert yaml.safe_load(output) == SCHEMA
+    assert output.startswith("openapi: 3.0.2\n")
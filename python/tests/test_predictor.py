@@ -1,10 +1,18 @@
+import functools
 import os
 import sys
-from typing import Optional
+from typing import List, Optional
 from unittest.mock import patch
 
-from cog import File, Path
-from cog.predictor import get_weights_type, load_predictor_from_ref
+import pytest
+from cog import File, Input, Path
+from cog.predictor import (
+    get_input_type,
+    get_output_type,
+    get_weights_type,
+    load_predictor_from_ref,
+)
+from pydantic import ValidationError
 
 
 def test_get_weights_type() -> None:
@@ -29,6 +37,150 @@ def test_get_weights_type() -> None:
     assert get_weights_type(f) == File
 
 
+def test_optional_list_input() -> None:
+    def predict(paths: Optional[List[Path]] = Input(default=None)) -> str:
+        return ""
+
+    InputType = get_input_type(predict)
+
+    assert InputType().paths is None
+    assert InputType.schema()["properties"]["paths"] == {
+        "title": "Paths",
+        "type": "array",
+        "items": {"type": "string", "format": "uri"},
+        "nullable": True,
+        "x-order": 0,
+    }
+
+
+def test_list_of_optional_input() -> None:
+    def predict(names: List[Optional[str]]) -> str:
+        return ""
+
+    InputType = get_input_type(predict)
+
+    assert InputType(names=["a", None]).names == ["a", None]
+    schema = InputType.schema()
+    assert schema["required"] == ["names"]
+    assert schema["properties"]["names"] == {
+        "title": "Names",
+        "type": "array",
+        "items": {"type": "string", "nullable": True},
+        "x-order": 0,
+    }
+
+
+def test_optional_input() -> None:
+    def predict(text: Optional[str] = None) -> str:
+        return ""
+
+    schema = get_input_type(predict).schema()
+
+    assert schema["properties"]["text"] == {
+        "title": "Text",
+        "type": "string",
+        "nullable": True,
+        "x-order": 0,
+    }
+
+
+def test_deeply_nested_input_type_is_unsupported() -> None:
+    def predict(paths: Optional[List[List[Path]]] = None) -> str:
+        return ""
+
+    with pytest.raises(TypeError) as e:
+        get_input_type(predict)
+
+    assert "parameter `paths`" in str(e.value)
+    assert "typing.Optional[typing.List[typing.List[cog.types.Path]]]" in str(e.value)
+
+
+def test_choices_input_validation() -> None:
+    def predict(
+        color: str = Input(choices=["red", "green", "blue"]),
+        size: int = Input(choices=[1, 2, 3]),
+    ) -> str:
+        return ""
+
+    InputType = get_input_type(predict)
+
+    valid = InputType(color="green", size=2)
+    assert valid.color == "green"
+    assert valid.size == 2
+
+    with pytest.raises(ValidationError) as e:
+        InputType(color="purple", size=4)
+
+    errors = e.value.errors()
+    assert [error["loc"] for error in errors] == [("color",), ("size",)]
+    assert "'red', 'green', 'blue'" in errors[0]["msg"]
+    assert "1, 2, 3" in errors[1]["msg"]
+
+    with pytest.raises(ValidationError):
+        InputType(color="Red", size=1)
+
+
+def test_default_factory_input() -> None:
+    def predict(
+        names: List[str] = Input(default_factory=list),
+        tags: List[str] = Input(default_factory=set),
+    ) -> str:
+        return ""
+
+    InputType = get_input_type(predict)
+
+    first = InputType()
+    assert first.names == []
+    assert first.tags == []
+    first.names.append("a")
+    assert InputType().names == []
+
+    schema = InputType.schema()
+    assert "required" not in schema
+    assert schema["properties"]["names"]["default"] == []
+    assert schema["properties"]["tags"]["default"] == []
+
+
+def test_default_factory_must_be_a_known_factory() -> None:
+    def make_names() -> List[str]:
+        return ["a"]
+
+    with pytest.raises(TypeError) as e:
+        Input(default_factory=make_names)
+
+    assert "Unsupported default_factory" in str(e.value)
+
+    with pytest.raises(TypeError):
+        Input(default=[], default_factory=list)
+
+
+def _passthrough(f):
+    @functools.wraps(f)
+    def wrapper(*args, **kwargs):
+        return f(*args, **kwargs)
+
+    return wrapper
+
+
+def test_decorated_predict_has_same_types() -> None:
+    def predict(text: str = Input(description="Text"), count: int = 1) -> List[str]:
+        return [text] * count
+
+    decorated = _passthrough(_passthrough(predict))
+
+    assert get_input_type(decorated).schema() == get_input_type(predict).schema()
+    assert get_output_type(decorated).schema() == get_output_type(predict).schema()
+
+
+def test_output_example_must_match_output_type() -> None:
+    predictor = load_predictor_from_ref(_fixture_path("output_example_mismatch"))
+
+    with pytest.raises(TypeError) as e:
+        get_output_type(predictor)
+
+    assert "COG_OUTPUT_EXAMPLE does not match" in str(e.value)
+
+
 def test_load_predictor_from_ref_overrides_argv():
     with patch("sys.argv", ["foo.py", "exec", "--giraffes=2", "--eat-cookies"]):
         predictor = load_predictor_from_ref(_fixture_path("argv_override"))
@@ -1,9 +1,18 @@
 import io
 import pickle
+from unittest import mock
 
 import pytest
 import responses
-from cog.types import Secret, URLFile, get_filename
+from cog.types import (
+    File,
+    Input,
+    Path,
+    Secret,
+    URLFile,
+    get_filename,
+    register_download_backend,
+)
 
 
 @responses.activate
@@ -66,6 +75,53 @@ def test_urlfile_can_be_pickled_even_once_loaded():
     assert isinstance(result, URLFile)
 
 
+def test_path_content_type_survives_pickling():
+    p = Path("/tmp/image").with_content_type("image/png")
+
+    result = pickle.loads(pickle.dumps(p))
+
+    assert isinstance(result, Path)
+    assert result == p
+    assert result.content_type == "image/png"
+    assert pickle.loads(pickle.dumps(Path("/tmp/image"))).content_type is None
+
+
+def test_input_when_must_name_a_field():
+    with pytest.raises(TypeError):
+        Input(when="mode")
+    with pytest.raises(TypeError):
+        Input(when={"equals": "advanced"})
+
+
+def test_urlfile_uses_registered_download_backend():
+    with mock.patch.dict("cog.types._download_backends"):
+        register_download_backend("s3", lambda url: io.BytesIO(url.encode()))
+
+        u = File.validate("s3://bucket/key")
+
+        assert isinstance(u, URLFile)
+        assert u.read() == b"s3://bucket/key"
+
+
+def test_path_inputs_are_downloaded_to_cog_tmpdir(tmp_path, monkeypatch):
+    tmpdir = tmp_path / "cog-tmp"
+    monkeypatch.setenv("COG_TMPDIR", str(tmpdir))
+
+    path = Path.validate("data:text/plain;base64,aGVsbG8=")
+    converted = path.convert()
+
+    assert converted.parent == tmpdir
+    assert converted.read_text() == "hello"
+
+
+def test_file_unknown_url_scheme():
+    with pytest.raises(ValueError) as e:
+        File.validate("ftp://example.com/foo.txt")
+
+    assert "'ftp' is not a valid URL scheme" in str(e.value)
+    assert "'s3'" in str(e.value)
+
+
 @pytest.mark.parametrize(
     "url,filename",
     [